@@ -0,0 +1,643 @@
+use bevy::utils::HashMap;
+
+use super::playback::{Song, SongNote, Track};
+use super::chords::{Chord, CHORD_TYPES, parse_chord_symbol};
+use super::staff::Key;
+
+// half tones from C of each letter
+static LETTERS: [(char, i32); 7] = [('C', 0), ('D', 2), ('E', 4), ('F', 5), ('G', 7), ('A', 9), ('B', 11)];
+
+// the letters getting a sharp, then a flat, in a key signature
+const SHARPS_ORDER: &str = "FCGDAEB";
+const FLATS_ORDER: &str = "BEADGCF";
+
+// how far above the major tonic is the tonic of each mode
+// "m" alone is the last one, after "maj" and "mix"
+static MODES: [(&str, i32); 10] = [
+    ("maj", 0), ("ion", 0), ("min", 9), ("aeo", 9), ("dor", 2),
+    ("phr", 4), ("lyd", 5), ("mix", 7), ("loc", 11), ("m", 9),
+];
+
+// the chord symbols are played around this midi note
+const CHORD_OCTAVE: i32 = 48;
+const MIDDLE_C: i32 = 60;
+
+// what is written in the body of a tune
+#[derive(Clone, Debug)]
+enum Item {
+    // midi numbers, with the length in whole notes, and whether they are tied to the next ones
+    Notes(Vec<i32>, f32, bool),
+    Rest(f32),
+    Symbol(Chord),
+    Bar,
+    RepeatStart,
+    RepeatEnd,
+    Ending(usize),
+}
+
+// the tune being read
+struct Tune {
+    title: Option<String>,
+    // the length of a note without a number, in whole notes
+    unit: f32,
+    // whether the unit was given with `L:`, otherwise it depends on the meter
+    unit_given: bool,
+    // the length of a measure, in whole notes
+    meter: f32,
+    // quarter notes per minute
+    tempo: f32,
+    // the accidental of each letter in the key signature
+    key: HashMap<char, i32>,
+    items: Vec<Item>,
+}
+
+fn parse_fraction(text: &str) -> Option<f32> {
+    let (a, b) = text.trim().split_once('/')?;
+    Some(a.trim().parse::<f32>().ok()? / b.trim().parse::<f32>().ok()?)
+}
+
+fn parse_key(text: &str) -> HashMap<char, i32> {
+    let text = text.trim();
+    let mut signature = HashMap::new();
+
+    let mut chars = text.chars();
+    let tonic = match chars.next().and_then(|c| LETTERS.iter().find(|(l, _)| *l == c.to_ascii_uppercase())) {
+        Some((_, t)) => *t,
+        None => return signature
+    };
+    let rest: String = chars.collect();
+    let (shift, rest) = match rest.chars().next() {
+        Some('#') => (1, &rest[1..]),
+        Some('b') => (-1, &rest[1..]),
+        _ => (0, &rest[..]),
+    };
+    let mode = rest.split_whitespace().next().unwrap_or("").to_lowercase();
+    let offset = MODES.iter().find(|(m, _)| mode.starts_with(m)).map_or(0, |(_, o)| *o);
+
+    let fifths = Key::major((tonic + shift - offset).rem_euclid(12)).signature;
+    let (order, accidental) = if fifths >= 0 {(SHARPS_ORDER, 1)} else {(FLATS_ORDER, -1)};
+    for letter in order.chars().take(fifths.unsigned_abs() as usize) {
+        signature.insert(letter, accidental);
+    }
+    signature
+}
+
+// a number, a fraction, or slashes after a note, as a multiple of the unit length
+fn parse_length(chars: &[char], i: &mut usize) -> f32 {
+    let number = |i: &mut usize| {
+        let start = *i;
+        while *i < chars.len() && chars[*i].is_ascii_digit() {
+            *i += 1;
+        }
+        chars[start..*i].iter().collect::<String>().parse::<f32>().ok()
+    };
+
+    let mut length = number(i).unwrap_or(1.);
+    while *i < chars.len() && chars[*i] == '/' {
+        *i += 1;
+        length /= number(i).unwrap_or(2.);
+    }
+    length
+}
+
+impl Tune {
+    fn new() -> Self {
+        Tune {title: None, unit: 1. / 8., unit_given: false, meter: 1., tempo: 120., key: HashMap::new(), items: Vec::new()}
+    }
+
+    fn field(&mut self, name: char, value: &str) {
+        match name {
+            'T' if self.title.is_none() => self.title = Some(value.trim().to_string()),
+            'L' => {
+                if let Some(unit) = parse_fraction(value) {
+                    self.unit = unit;
+                    self.unit_given = true;
+                }
+            }
+            'M' => {
+                let meter = match value.trim() {
+                    "C" | "C|" => Some(1.),
+                    m => parse_fraction(m),
+                };
+                if let Some(meter) = meter {
+                    self.meter = meter;
+                    // short measures have sixteenths as their unit, unless another one is given
+                    if !self.unit_given {
+                        self.unit = if meter < 0.75 {1. / 16.} else {1. / 8.};
+                    }
+                }
+            }
+            // like "1/4=120", or just "120"
+            'Q' => {
+                let (beat, bpm) = match value.split_once('=') {
+                    Some((beat, bpm)) => (parse_fraction(beat).unwrap_or(0.25), bpm),
+                    None => (0.25, value),
+                };
+                if let Ok(bpm) = bpm.trim().parse::<f32>() {
+                    self.tempo = bpm * beat * 4.;
+                }
+            }
+            'K' => self.key = parse_key(value),
+            _ => {}
+        }
+    }
+
+    fn body(&mut self, line: &str) -> Result<(), String> {
+        let chars: Vec<char> = line.chars().collect();
+        let mut i = 0;
+        // the accidentals written in the current measure, by letter and octave
+        let mut measure: HashMap<(char, i32), i32> = HashMap::new();
+        // the number of notes left in a tuplet, and their length ratio
+        let mut tuplet: Option<(usize, f32)> = None;
+        // the ratio of the next note after a broken rhythm
+        let mut broken = 1.;
+
+        while i < chars.len() {
+            let c = chars[i];
+            match c {
+                '%' => break,
+                '"' => {
+                    let end = chars[i + 1..].iter().position(|&c| c == '"').ok_or("unclosed chord symbol")?;
+                    let text: String = chars[i + 1..i + 1 + end].iter().collect();
+                    // the annotations are not chords
+                    if let Some(chord) = parse_chord_symbol(text.trim()) {
+                        self.items.push(Item::Symbol(chord));
+                    }
+                    i += end + 2;
+                }
+                '!' | '+' => {
+                    let end = chars[i + 1..].iter().position(|&d| d == c).ok_or("unclosed decoration")?;
+                    i += end + 2;
+                }
+                // grace notes are not played
+                '{' => {
+                    let end = chars[i..].iter().position(|&c| c == '}').ok_or("unclosed grace notes")?;
+                    i += end + 1;
+                }
+                '(' if chars.get(i + 1).is_some_and(|c| c.is_ascii_digit()) => {
+                    let p = chars[i + 1].to_digit(10).unwrap() as usize;
+                    if p < 2 {
+                        return Err(format!("wrong tuplet \"({}\"", p))
+                    }
+                    let q = match p {2 | 4 | 8 => 3., _ => 2.};
+                    tuplet = Some((p, q / p as f32));
+                    i += 2;
+                }
+                '[' if chars.get(i + 1).is_some_and(|c| c.is_ascii_digit()) => {
+                    self.items.push(Item::Ending(chars[i + 1].to_digit(10).unwrap() as usize));
+                    i += 2;
+                }
+                // an inline field, like [K:D], named by a letter
+                '[' if chars.get(i + 1).is_some_and(|c| c.is_ascii_alphabetic()) && chars.get(i + 2) == Some(&':') => {
+                    let end = chars[i..].iter().position(|&c| c == ']').ok_or("unclosed field")?;
+                    let value: String = chars[i + 3..i + end].iter().collect();
+                    self.field(chars[i + 1], &value);
+                    i += end + 1;
+                }
+                '|' | ':' | '[' if c != '[' || chars.get(i + 1) == Some(&'|') => {
+                    let start = i;
+                    while i < chars.len() && match chars[i] {
+                        '|' | ':' => true,
+                        ']' => i > start,
+                        '[' => chars.get(i + 1) == Some(&'|'),
+                        _ => false,
+                    } {
+                        i += 1;
+                    }
+                    let bar: String = chars[start..i].iter().collect();
+                    if bar.starts_with(':') {
+                        self.items.push(Item::RepeatEnd);
+                    }
+                    if bar.ends_with(':') {
+                        self.items.push(Item::RepeatStart);
+                    }
+                    self.items.push(Item::Bar);
+                    measure.clear();
+                    if let Some(n) = chars.get(i).and_then(|c| c.to_digit(10)) {
+                        self.items.push(Item::Ending(n as usize));
+                        i += 1;
+                    }
+                }
+                '>' | '<' => {
+                    let (before, after) = if c == '>' {(1.5, 0.5)} else {(0.5, 1.5)};
+                    let previous = self.items.iter_mut().rev().find(|it| matches!(it, Item::Notes(..) | Item::Rest(_)));
+                    match previous {
+                        Some(Item::Notes(_, length, _)) | Some(Item::Rest(length)) => *length *= before,
+                        _ => {}
+                    }
+                    broken = after;
+                    i += 1;
+                }
+                '-' => {
+                    if let Some(Item::Notes(_, _, tied)) = self.items.last_mut() {
+                        *tied = true;
+                    }
+                    i += 1;
+                }
+                // as many whole measures as the number after it
+                'Z' | 'X' => {
+                    i += 1;
+                    let length = parse_length(&chars, &mut i) * self.meter;
+                    self.items.push(Item::Rest(length));
+                }
+                'z' | 'x' => {
+                    i += 1;
+                    let mut length = parse_length(&chars, &mut i) * self.unit * broken;
+                    if let Some((n, ratio)) = &mut tuplet {
+                        length *= *ratio;
+                        *n -= 1;
+                    }
+                    tuplet = tuplet.filter(|(n, _)| *n > 0);
+                    broken = 1.;
+                    self.items.push(Item::Rest(length));
+                }
+                '[' | '^' | '_' | '=' | 'a'..='g' | 'A'..='G' => {
+                    let chord = c == '[';
+                    if chord {
+                        i += 1;
+                    }
+
+                    let mut pitches = Vec::new();
+                    let mut length = None;
+                    loop {
+                        // the notes of a chord can be spaced
+                        while chord && chars.get(i).is_some_and(|c| c.is_whitespace()) {
+                            i += 1;
+                        }
+                        if chord && (chars.get(i) == Some(&']') || i >= chars.len()) {
+                            break
+                        }
+                        let (pitch, l) = self.note(&chars, &mut i, &mut measure)?;
+                        pitches.push(pitch);
+                        length = length.or(Some(l));
+                        if !chord {
+                            break
+                        }
+                    }
+                    if chord {
+                        if chars.get(i) != Some(&']') {
+                            return Err("unclosed chord".to_string())
+                        }
+                        if pitches.is_empty() {
+                            return Err("empty chord".to_string())
+                        }
+                        i += 1;
+                        length = Some(length.unwrap_or(1.) * parse_length(&chars, &mut i));
+                    }
+
+                    let mut length = length.unwrap_or(1.) * self.unit * broken;
+                    if let Some((n, ratio)) = &mut tuplet {
+                        length *= *ratio;
+                        *n -= 1;
+                    }
+                    tuplet = tuplet.filter(|(n, _)| *n > 0);
+                    broken = 1.;
+                    self.items.push(Item::Notes(pitches, length, false));
+                }
+                _ => i += 1,
+            }
+        }
+
+        Ok(())
+    }
+
+    // one note with its accidental, octave and length, the length being a multiple of the unit
+    fn note(&self, chars: &[char], i: &mut usize, measure: &mut HashMap<(char, i32), i32>) -> Result<(i32, f32), String> {
+        let mut accidental = None;
+        while let Some(&c) = chars.get(*i).filter(|c| matches!(c, '^' | '_' | '=')) {
+            let a = accidental.unwrap_or(0);
+            accidental = Some(match c {'^' => a + 1, '_' => a - 1, _ => 0});
+            *i += 1;
+        }
+
+        let c = *chars.get(*i).ok_or("missing note")?;
+        let (letter, semitones) = LETTERS.iter().find(|(l, _)| *l == c.to_ascii_uppercase())
+            .ok_or(format!("unexpected character \"{}\"", c))?;
+        *i += 1;
+
+        let mut octave = if c.is_ascii_lowercase() {1} else {0};
+        while let Some(&c) = chars.get(*i).filter(|c| matches!(c, '\'' | ',')) {
+            octave += if c == '\'' {1} else {-1};
+            *i += 1;
+        }
+
+        // an accidental lasts until the end of the measure
+        let accidental = match accidental {
+            Some(a) => {
+                measure.insert((*letter, octave), a);
+                a
+            }
+            None => measure.get(&(*letter, octave)).or(self.key.get(letter)).copied().unwrap_or(0),
+        };
+
+        Ok((MIDDLE_C + 12 * octave + semitones + accidental, parse_length(chars, i)))
+    }
+}
+
+// Plays the repeats, and the endings in their turn.
+// The pass is 0 after a repeat, when no ending is skipped.
+fn unroll(items: &[Item]) -> Vec<Item> {
+    let mut played = Vec::new();
+    let mut start = 0;
+    let mut pass = 1;
+    let mut skipping = false;
+    let mut i = 0;
+
+    while i < items.len() {
+        match items[i] {
+            Item::RepeatStart => {
+                start = i + 1;
+                pass = 1;
+            }
+            Item::RepeatEnd if pass != 2 => {
+                pass = 2;
+                skipping = false;
+                i = start;
+                continue
+            }
+            // the repeat is over, and the next one starts from here
+            Item::RepeatEnd => {
+                start = i + 1;
+                pass = 0;
+                skipping = false;
+            }
+            Item::Ending(n) => skipping = pass != 0 && n != pass,
+            ref item if !skipping => played.push(item.clone()),
+            _ => {}
+        }
+        i += 1;
+    }
+
+    played
+}
+
+// the midi notes of a chord symbol, the root being in the octave of `CHORD_OCTAVE`
+fn chord_pitches(chord: &Chord) -> Vec<i32> {
+    // the chords count their notes from A
+    let from_c = |note: usize| (note as i32 + 9) % 12;
+    let root = CHORD_OCTAVE + from_c(chord.root);
+    let mut pitches: Vec<i32> = CHORD_TYPES[chord.chord_type].intervals.iter().map(|&i| root + i as i32).collect();
+    if let Some(bass) = chord.bass {
+        pitches.push(CHORD_OCTAVE - 12 + from_c(bass));
+    }
+    pitches
+}
+
+fn play_note(song: &mut Song, pitch: i32, start: f32, end: f32) {
+    song.notes.push(SongNote {start, duration: end - start, pitch: pitch.clamp(0, 127) as u8, track: 0});
+}
+
+// the tied notes not going on with one of `pitches` end at `time`
+fn end_ties(song: &mut Song, tied: &mut HashMap<i32, f32>, pitches: &[i32], time: f32) {
+    let ended: Vec<i32> = tied.keys().copied().filter(|p| !pitches.contains(p)).collect();
+    for pitch in ended {
+        let start = tied.remove(&pitch).unwrap_or(time);
+        play_note(song, pitch, start, time);
+    }
+}
+
+// a chord symbol lasts until the next one
+fn play_symbol(song: &mut Song, symbol: Option<(f32, Chord)>, end: f32) {
+    if let Some((start, chord)) = symbol {
+        for pitch in chord_pitches(&chord) {
+            song.notes.push(SongNote {start, duration: end - start, pitch: pitch as u8, track: 1});
+        }
+    }
+}
+
+// Reads the first tune of an ABC file.
+// The melody and the chord symbols are two tracks of the song.
+pub fn parse_abc(text: &str, name: &str) -> Result<Song, String> {
+    let mut tune = Tune::new();
+    let mut in_body = false;
+
+    for line in text.lines() {
+        let line = line.trim_end().trim_end_matches('\\');
+        let is_field = line.len() >= 2 && line.as_bytes()[1] == b':' && line.as_bytes()[0].is_ascii_alphabetic();
+
+        if is_field {
+            // the next tune starts
+            if in_body && line.starts_with("X:") {
+                break
+            }
+            let name = line.as_bytes()[0] as char;
+            tune.field(name, &line[2..]);
+            in_body |= name == 'K';
+        }
+        else if in_body && !line.trim().is_empty() {
+            tune.body(line)?;
+        }
+    }
+
+    let mut song = Song {name: tune.title.clone().unwrap_or(name.to_string()), ..Default::default()};
+    song.tracks.push(Track {name: "melody".to_string(), muted: false});
+    song.tracks.push(Track {name: "chords".to_string(), muted: false});
+
+    // whole notes to seconds
+    let seconds = 4. * 60. / tune.tempo;
+    let mut time = 0.;
+    // the notes tied to the next ones, with their start
+    let mut tied: HashMap<i32, f32> = HashMap::new();
+    let mut symbol: Option<(f32, Chord)> = None;
+
+    for item in unroll(&tune.items) {
+        match item {
+            Item::Notes(pitches, length, tie) => {
+                end_ties(&mut song, &mut tied, &pitches, time);
+                let duration = length * seconds;
+                for pitch in pitches {
+                    let start = tied.remove(&pitch).unwrap_or(time);
+                    if tie {
+                        tied.insert(pitch, start);
+                    }
+                    else {
+                        play_note(&mut song, pitch, start, time + duration);
+                    }
+                }
+                time += duration;
+            }
+            Item::Rest(length) => {
+                end_ties(&mut song, &mut tied, &[], time);
+                time += length * seconds;
+            }
+            Item::Symbol(chord) => {
+                play_symbol(&mut song, symbol.take(), time);
+                song.chords.push((time, chord.clone()));
+                symbol = Some((time, chord));
+            }
+            _ => {}
+        }
+    }
+    play_symbol(&mut song, symbol, time);
+    end_ties(&mut song, &mut tied, &[], time);
+
+    if song.notes.is_empty() {
+        return Err("no notes in the tune".to_string())
+    }
+
+    song.notes.sort_by(|a, b| a.start.total_cmp(&b.start));
+    Ok(song)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // the notes of a tune with the header given, in milliseconds,
+    // with their pitch and track, at 120 quarter notes per minute
+    fn notes(header: &str, body: &str) -> Vec<(i32, i32, u8, usize)> {
+        let song = parse_abc(&format!("X:1\n{}\n{}\n", header, body), "test").unwrap();
+        let ms = |t: f32| (t * 1000.).round() as i32;
+        let mut notes: Vec<_> = song.notes.iter().map(|n| (ms(n.start), ms(n.duration), n.pitch, n.track)).collect();
+        notes.sort();
+        notes
+    }
+
+    fn pitches(header: &str, body: &str) -> Vec<u8> {
+        notes(header, body).iter().map(|n| n.2).collect()
+    }
+
+    #[test]
+    fn key_signatures_and_modes() {
+        let sharps = |text: &str| {
+            let mut key: Vec<(char, i32)> = parse_key(text).into_iter().collect();
+            key.sort();
+            key
+        };
+        assert_eq!(sharps("C"), vec![]);
+        assert_eq!(sharps("D"), vec![('C', 1), ('F', 1)]);
+        assert_eq!(sharps("Bb"), vec![('B', -1), ('E', -1)]);
+        assert_eq!(sharps("F#"), vec![('A', 1), ('C', 1), ('D', 1), ('E', 1), ('F', 1), ('G', 1)]);
+        // the modes have the signature of their major key
+        assert_eq!(sharps("Am"), vec![]);
+        assert_eq!(sharps("Dm"), vec![('B', -1)]);
+        assert_eq!(sharps("E dor"), vec![('C', 1), ('F', 1)]);
+        assert_eq!(sharps("A Mixolydian"), vec![('C', 1), ('F', 1)]);
+        assert_eq!(sharps("F#m"), vec![('C', 1), ('F', 1), ('G', 1)]);
+        assert_eq!(sharps("B loc"), vec![]);
+    }
+
+    #[test]
+    fn accidentals() {
+        assert_eq!(pitches("K:D", "F f C"), vec![66, 78, 61]);
+        assert_eq!(pitches("K:C", "^C _D =E ^^F __B"), vec![61, 61, 64, 67, 69]);
+        // an accidental lasts until the end of the measure, in its octave
+        assert_eq!(pitches("K:C", "^c c C | c"), vec![73, 73, 60, 72]);
+        assert_eq!(pitches("K:D", "=F F | F"), vec![65, 65, 66]);
+        assert_eq!(pitches("K:C", "C, C c c'"), vec![48, 60, 72, 84]);
+    }
+
+    #[test]
+    fn unit_lengths() {
+        // an eighth by default, at a quarter note every 500 ms
+        assert_eq!(notes("K:C", "C C2 C/ C3/2")[..2], [(0, 250, 60, 0), (250, 500, 60, 0)]);
+        assert_eq!(notes("K:C", "C/ C//")[1], (125, 63, 60, 0));
+        assert_eq!(notes("L:1/4\nK:C", "C C")[1], (500, 500, 60, 0));
+        // a sixteenth in short measures
+        assert_eq!(notes("M:2/4\nK:C", "C C")[1], (125, 125, 60, 0));
+        assert_eq!(notes("M:3/4\nK:C", "C C")[1], (250, 250, 60, 0));
+        // unless one is given, before or after the meter
+        assert_eq!(notes("L:1/4\nM:2/4\nK:C", "C C")[1], (500, 500, 60, 0));
+        assert_eq!(notes("M:2/4\nL:1/4\nK:C", "C C")[1], (500, 500, 60, 0));
+        assert_eq!(notes("L:1/4\nK:C", "C [M:2/4] C")[1], (500, 500, 60, 0));
+        assert_eq!(notes("Q:1/8=120\nK:C", "C C")[1], (500, 500, 60, 0));
+    }
+
+    #[test]
+    fn measure_rests() {
+        assert_eq!(notes("M:3/4\nL:1/4\nK:C", "Z C"), vec![(1500, 500, 60, 0)]);
+        assert_eq!(notes("M:3/4\nL:1/4\nK:C", "Z2 | C"), vec![(3000, 500, 60, 0)]);
+        assert_eq!(notes("M:C|\nL:1/4\nK:C", "X C"), vec![(2000, 500, 60, 0)]);
+        assert_eq!(notes("L:1/4\nK:C", "z2 x C"), vec![(1500, 500, 60, 0)]);
+    }
+
+    #[test]
+    fn repeats_and_endings() {
+        assert_eq!(pitches("K:C", "|: C D :| E"), vec![60, 62, 60, 62, 64]);
+        assert_eq!(pitches("K:C", "C |: D :: E :| F"), vec![60, 62, 62, 64, 64, 65]);
+        assert_eq!(pitches("K:C", "|: C |1 D :|2 E |"), vec![60, 62, 60, 64]);
+        assert_eq!(pitches("K:C", "|: C [1 D :| [2 E |]"), vec![60, 62, 60, 64]);
+        // a repeat without its start goes back to the beginning
+        assert_eq!(pitches("K:C", "C D :| E"), vec![60, 62, 60, 62, 64]);
+    }
+
+    #[test]
+    fn tuplets() {
+        let triplet = notes("L:1/4\nK:C", "(3CDE F");
+        let starts: Vec<i32> = triplet.iter().map(|n| n.0).collect();
+        assert_eq!(starts, vec![0, 333, 667, 1000]);
+        let duplet = notes("L:1/4\nK:C", "(2CD E");
+        assert_eq!(duplet[2].0, 1500);
+        for tuplet in ["(0CDE", "(1C"] {
+            let tune = format!("X:1\nK:C\n{}\n", tuplet);
+            assert!(parse_abc(&tune, "test").is_err(), "{}", tuplet);
+        }
+    }
+
+    #[test]
+    fn broken_rhythm() {
+        assert_eq!(notes("L:1/4\nK:C", "C>D"), vec![(0, 750, 60, 0), (750, 250, 62, 0)]);
+        assert_eq!(notes("L:1/4\nK:C", "C<D"), vec![(0, 250, 60, 0), (250, 750, 62, 0)]);
+    }
+
+    #[test]
+    fn ties() {
+        assert_eq!(notes("L:1/4\nK:C", "C-C D"), vec![(0, 1000, 60, 0), (1000, 500, 62, 0)]);
+        assert_eq!(notes("L:1/4\nK:C", "C-|C"), vec![(0, 1000, 60, 0)]);
+        assert_eq!(notes("L:1/4\nK:C", "[CE]-[CE]"), vec![(0, 1000, 60, 0), (0, 1000, 64, 0)]);
+        // a tie to another pitch ends the note
+        assert_eq!(notes("L:1/4\nK:C", "C-D E"), vec![(0, 500, 60, 0), (500, 500, 62, 0), (1000, 500, 64, 0)]);
+        assert_eq!(notes("L:1/4\nK:C", "[CE]-[CG]"), vec![(0, 500, 64, 0), (0, 1000, 60, 0), (500, 500, 67, 0)]);
+        assert_eq!(notes("L:1/4\nK:C", "C- z D"), vec![(0, 500, 60, 0), (1000, 500, 62, 0)]);
+        // a tie at the end lasts to the end of the tune
+        assert_eq!(notes("L:1/4\nK:C", "C D-"), vec![(0, 500, 60, 0), (500, 500, 62, 0)]);
+    }
+
+    #[test]
+    fn chords() {
+        assert_eq!(notes("L:1/4\nK:C", "[CEG]2 [C E G ] C"), vec![
+            (0, 1000, 60, 0), (0, 1000, 64, 0), (0, 1000, 67, 0),
+            (1000, 500, 60, 0), (1000, 500, 64, 0), (1000, 500, 67, 0),
+            (1500, 500, 60, 0),
+        ]);
+        for chord in ["[CE", "[]", "[ ] C"] {
+            let tune = format!("X:1\nK:C\n{}\n", chord);
+            assert!(parse_abc(&tune, "test").is_err(), "{}", chord);
+        }
+    }
+
+    #[test]
+    fn inline_fields() {
+        assert_eq!(pitches("K:C", "F [K:D] F"), vec![65, 66]);
+        assert_eq!(notes("K:C", "C [L:1/4] C")[1], (250, 500, 60, 0));
+        // the brackets that only look like fields
+        assert_eq!(pitches("K:C", "[1: C"), vec![60]);
+        for body in ["[]: C", "[K C", "[K:"] {
+            let tune = format!("X:1\nK:C\n{}\n", body);
+            assert!(parse_abc(&tune, "test").is_err(), "{}", body);
+        }
+    }
+
+    #[test]
+    fn chord_symbols_are_played_on_their_track() {
+        let song = parse_abc("X:1\nT:Song\nL:1/4\nK:C\n\"C\" C D \"G7\" E \"not a chord\" F |\n", "test").unwrap();
+        assert_eq!(song.name, "Song");
+        assert_eq!(song.chords.iter().map(|(t, c)| (*t, c.root, c.chord_type)).collect::<Vec<_>>(), vec![(0., 3, 0), (1., 10, 2)]);
+
+        let chords: Vec<(i32, i32, u8)> = notes("L:1/4\nK:C", "\"C\" C D \"G7\" E \"not a chord\" F |").into_iter()
+            .filter(|n| n.3 == 1)
+            .map(|n| (n.0, n.1, n.2))
+            .collect();
+        assert_eq!(chords, vec![
+            (0, 1000, 48), (0, 1000, 52), (0, 1000, 55),
+            (1000, 1000, 55), (1000, 1000, 59), (1000, 1000, 62), (1000, 1000, 65),
+        ]);
+    }
+
+    #[test]
+    fn only_the_first_tune_is_read() {
+        let song = parse_abc("X:1\nK:C\nC\n\nX:2\nK:C\nD E\n", "test").unwrap();
+        assert_eq!(song.notes.len(), 1);
+        assert!(parse_abc("X:1\nT:no body\n", "test").is_err());
+    }
+}
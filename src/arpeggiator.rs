@@ -0,0 +1,228 @@
+use bevy::prelude::*;
+
+use super::{NotePosition, Held, N_OCTAVES};
+use super::transport::Transport;
+
+const LABEL_OFFSET: Vec3 = Vec3::new(-300., 270., 0.);
+
+// the arpeggiator plays eighth notes
+const STEPS_PER_BEAT: f32 = 2.;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum ArpeggioOrder {
+    Up,
+    Down,
+    UpDown,
+    Random,
+    AsPlayed,
+}
+
+impl ArpeggioOrder {
+    fn name(self) -> &'static str {
+        match self {
+            ArpeggioOrder::Up => "up",
+            ArpeggioOrder::Down => "down",
+            ArpeggioOrder::UpDown => "up-down",
+            ArpeggioOrder::Random => "random",
+            ArpeggioOrder::AsPlayed => "as played",
+        }
+    }
+
+    fn next(self) -> Self {
+        match self {
+            ArpeggioOrder::Up => ArpeggioOrder::Down,
+            ArpeggioOrder::Down => ArpeggioOrder::UpDown,
+            ArpeggioOrder::UpDown => ArpeggioOrder::Random,
+            ArpeggioOrder::Random => ArpeggioOrder::AsPlayed,
+            ArpeggioOrder::AsPlayed => ArpeggioOrder::Up,
+        }
+    }
+}
+
+// When enabled, the notes being held are not played together,
+// but one after the other, in time with the transport
+#[derive(Resource)]
+pub struct Arpeggiator {
+    enabled: bool,
+    order: ArpeggioOrder,
+    // over how many octaves the held notes are repeated
+    octaves: usize,
+    // the held notes, in the order they were pressed
+    held: Vec<usize>,
+    step: usize,
+    // the eighth of the transport when the last note was played
+    tick: Option<usize>,
+    current: Option<usize>,
+    random_state: u32,
+}
+
+impl Default for Arpeggiator {
+    fn default() -> Self {
+        Arpeggiator {
+            enabled: false,
+            order: ArpeggioOrder::Up,
+            octaves: 1,
+            held: Vec::new(),
+            step: 0,
+            tick: None,
+            current: None,
+            random_state: 0x9e3779b9,
+        }
+    }
+}
+
+impl Arpeggiator {
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    // whether the note should sound, given whether it is held
+    pub fn sounding(&self, position: usize, held: bool) -> bool {
+        if self.enabled {
+            self.current == Some(position)
+        }
+        else {
+            held
+        }
+    }
+
+    // xorshift, good enough to choose the next note
+    fn random(&mut self) -> usize {
+        let mut x = self.random_state;
+        x ^= x << 13;
+        x ^= x >> 17;
+        x ^= x << 5;
+        self.random_state = x;
+        x as usize
+    }
+
+    // the notes of one cycle of the arpeggio
+    fn pattern(&self) -> Vec<usize> {
+        let mut base = self.held.clone();
+        if self.order != ArpeggioOrder::AsPlayed {
+            base.sort();
+        }
+
+        let mut notes: Vec<usize> = (0..self.octaves)
+            .flat_map(|octave| base.iter().map(move |p| p + 12 * octave))
+            .filter(|&p| p < 12 * N_OCTAVES)
+            .collect();
+
+        match self.order {
+            ArpeggioOrder::Down => notes.reverse(),
+            ArpeggioOrder::UpDown => {
+                let down: Vec<usize> = notes.iter().rev().skip(1).take(notes.len().saturating_sub(2)).copied().collect();
+                notes.extend(down);
+            }
+            _ => {}
+        }
+
+        notes
+    }
+
+    fn advance(&mut self) {
+        let pattern = self.pattern();
+        if pattern.is_empty() {
+            self.current = None;
+            self.step = 0;
+            return
+        }
+
+        let i = if self.order == ArpeggioOrder::Random {self.random()} else {self.step};
+        self.current = Some(pattern[i % pattern.len()]);
+        self.step = (self.step + 1) % pattern.len();
+    }
+
+    fn label(&self) -> String {
+        if !self.enabled {
+            return "arpeggiator: off".to_string()
+        }
+        format!("arpeggiator: {}, {} octave(s)", self.order.name(), self.octaves)
+    }
+}
+
+#[derive(Component)]
+pub struct ArpeggiatorLabel;
+
+pub fn create_arpeggiator_label(
+    mut commands: Commands,
+    arpeggiator: Res<Arpeggiator>,
+) {
+    let text_style : TextStyle = TextStyle {
+        color: Color::GRAY,
+        font_size: 14.,
+        font: Default::default(),
+    };
+
+    let label = Text2dBundle {
+        text: Text::from_section(arpeggiator.label(), text_style),
+        transform: Transform::from_translation(LABEL_OFFSET),
+        ..default()
+    };
+
+    commands.spawn((label, ArpeggiatorLabel));
+}
+
+pub fn arpeggiator_input_system(
+    keyboard_input: Res<Input<KeyCode>>,
+    mut arpeggiator: ResMut<Arpeggiator>,
+    mut label: Query<&mut Text, With<ArpeggiatorLabel>>,
+) {
+    let a = &mut *arpeggiator;
+
+    if keyboard_input.just_pressed(KeyCode::F5) {
+        a.enabled = !a.enabled;
+        a.step = 0;
+        a.tick = None;
+        a.current = None;
+    }
+    if keyboard_input.just_pressed(KeyCode::F6) {
+        a.order = a.order.next();
+        a.step = 0;
+    }
+    if keyboard_input.just_pressed(KeyCode::F9) {
+        a.octaves = a.octaves % N_OCTAVES + 1;
+    }
+
+    if keyboard_input.any_just_pressed([KeyCode::F5, KeyCode::F6, KeyCode::F9]) {
+        for mut text in &mut label {
+            text.sections[0].value = a.label();
+        }
+    }
+}
+
+pub fn update_arpeggiator(
+    transport: Res<Transport>,
+    mut arpeggiator: ResMut<Arpeggiator>,
+    notes: Query<(&NotePosition, &Held)>,
+) {
+    let a = &mut *arpeggiator;
+
+    // keep the order in which the notes were pressed
+    let mut held: Vec<usize> = notes.iter().filter(|(_, h)| h.0 > 0).map(|(p, _)| p.0).collect();
+    held.sort();
+    a.held.retain(|p| held.contains(p));
+    for p in held {
+        if !a.held.contains(&p) {
+            a.held.push(p);
+        }
+    }
+
+    if !a.enabled {
+        return
+    }
+
+    if a.held.is_empty() || !transport.playing() {
+        a.current = None;
+        a.step = 0;
+        a.tick = None;
+        return
+    }
+
+    // start as soon as a note is pressed, then follow the eighths of the transport
+    let tick = (transport.position() * STEPS_PER_BEAT) as usize;
+    if a.current.is_none() || a.tick != Some(tick) {
+        a.tick = Some(tick);
+        a.advance();
+    }
+}
@@ -0,0 +1,430 @@
+use bevy::prelude::*;
+
+use std::path::{Path, PathBuf};
+
+use super::{NoteEvent, NotePosition, BaseNote, CommandLine};
+use super::circle::{dot_position, DOT_RADIUS};
+use super::playback::{Playback, load_song, circle_position};
+use super::sequencer::Sequencer;
+use super::arpeggiator::Arpeggiator;
+use super::looper::Looper;
+use super::quiz::Quiz;
+use super::text_input::TextEntry;
+
+const LABEL_OFFSET: Vec3 = Vec3::new(300., -270., 0.);
+
+// where the levels are looked for, when not given with `--levels`
+const DEFAULT_LEVELS: &str = "levels";
+
+// the time to get ready before the first note, in seconds
+const LEAD_IN: f32 = 3.;
+// how long before its start a note is shown
+const LOOK_AHEAD: f32 = 1.5;
+// how early or late a note can be played to count, in seconds
+const WINDOW: f32 = 0.3;
+// the points of a note played right on time, half of them being lost when it is at the edge of the window
+const NOTE_POINTS: f32 = 100.;
+// the points lost for each note played that was not asked
+const WRONG_PENALTY: f32 = 25.;
+// the score needed to go to the next level, in percent
+const PASS_SCORE: f32 = 60.;
+
+// a note of the level, with when it was played, relative to its start
+struct Target {
+    start: f32,
+    end: f32,
+    // its position follows the base note, which can be changed while playing
+    pitch: u8,
+    played: Option<f32>,
+}
+
+impl Target {
+    fn points(&self) -> f32 {
+        match self.played {
+            Some(offset) => NOTE_POINTS * (1. - 0.5 * offset.abs() / WINDOW),
+            None => 0.,
+        }
+    }
+}
+
+// The files of the levels directory that can be opened as songs, in the order of their names
+fn level_paths(dir: &Path) -> Vec<PathBuf> {
+    let mut paths: Vec<PathBuf> = match std::fs::read_dir(dir) {
+        Ok(entries) => entries.filter_map(|e| e.ok()).map(|e| e.path()).collect(),
+        Err(_) => return Vec::new()
+    };
+    paths.retain(|p| {
+        let extension = p.extension().and_then(|e| e.to_str()).map(|e| e.to_lowercase());
+        matches!(extension.as_deref(), Some("abc") | Some("mid") | Some("midi"))
+    });
+    paths.sort();
+    paths
+}
+
+// A level shows the notes to play on the circle, and scores how many are played, and how close to their time
+#[derive(Resource, Default)]
+pub struct Challenge {
+    levels: Vec<PathBuf>,
+    level: usize,
+    name: String,
+    // sorted by start
+    targets: Vec<Target>,
+    running: bool,
+    // in seconds since the first note could be played, negative before
+    time: f32,
+    wrong: usize,
+    // what else is playing notes, which would be scored as the player's
+    paused: Option<&'static str>,
+    message: Option<String>,
+}
+
+impl Challenge {
+    fn start(&mut self, dir: &Path) {
+        self.levels = level_paths(dir);
+        // the levels may have changed since the last one was passed
+        if self.level >= self.levels.len() {
+            self.level = 0;
+        }
+        let path = match self.levels.get(self.level) {
+            Some(p) => p.clone(),
+            None => {
+                self.message = Some(format!("no level in {}", dir.display()));
+                return
+            }
+        };
+
+        let song = match load_song(&path) {
+            Ok(s) => s,
+            Err(e) => {
+                self.message = Some(e);
+                return
+            }
+        };
+
+        self.targets = song.notes.iter()
+            .filter(|n| !song.tracks[n.track].muted)
+            .map(|n| Target {
+                start: n.start,
+                end: n.start + n.duration,
+                pitch: n.pitch,
+                played: None,
+            })
+            .collect();
+        self.targets.sort_by(|a, b| a.start.total_cmp(&b.start).then(a.pitch.cmp(&b.pitch)));
+        self.targets.dedup_by(|a, b| a.start == b.start && a.pitch == b.pitch);
+
+        self.name = song.name;
+        self.running = true;
+        self.time = -LEAD_IN;
+        self.wrong = 0;
+        self.message = None;
+    }
+
+    // The note played counts for the closest note of the level not played yet, at the same position,
+    // and for the ones starting with it that the octaves moved to the same position
+    fn play(&mut self, position: usize, base_note: usize) {
+        let time = self.time;
+        let start = self.targets.iter()
+            .filter(|t| circle_position(t.pitch, base_note) == position && t.played.is_none() && (t.start - time).abs() <= WINDOW)
+            .map(|t| t.start)
+            .min_by(|a, b| (a - time).abs().total_cmp(&(b - time).abs()));
+
+        let start = match start {
+            Some(s) => s,
+            None => {
+                self.wrong += 1;
+                return
+            }
+        };
+        for t in self.targets.iter_mut().filter(|t| t.start == start && circle_position(t.pitch, base_note) == position) {
+            t.played.get_or_insert(time - start);
+        }
+    }
+
+    fn score(&self) -> f32 {
+        if self.targets.is_empty() {
+            return 0.
+        }
+        let points: f32 = self.targets.iter().map(|t| t.points()).sum::<f32>() - WRONG_PENALTY * self.wrong as f32;
+        (100. * points / (NOTE_POINTS * self.targets.len() as f32)).clamp(0., 100.)
+    }
+
+    fn end(&self) -> f32 {
+        self.targets.iter().map(|t| t.end).fold(0., f32::max)
+    }
+
+    // the level is over, the next one is chosen if it was passed
+    fn finish(&mut self) {
+        self.running = false;
+        let score = self.score();
+
+        self.message = Some(if score < PASS_SCORE {
+            format!("{} failed with {:.0}%, try again", self.name, score)
+        }
+        else if self.level + 1 < self.levels.len() {
+            self.level += 1;
+            format!("{} passed with {:.0}%, next level", self.name, score)
+        }
+        else {
+            self.level = 0;
+            format!("{} passed with {:.0}%, all levels done", self.name, score)
+        });
+    }
+
+    fn label(&self) -> String {
+        if let Some(other) = self.paused.filter(|_| self.running) {
+            return format!("challenge {}/{}: paused while {} plays", self.level + 1, self.levels.len(), other)
+        }
+
+        if !self.running {
+            return match &self.message {
+                Some(m) => format!("challenge: {}", m),
+                None => "challenge: delete to start".to_string(),
+            }
+        }
+
+        if self.time < 0. {
+            return format!("challenge {}/{}: {}, ready in {:.0}", self.level + 1, self.levels.len(), self.name, -self.time.floor())
+        }
+
+        let played: Vec<f32> = self.targets.iter().filter_map(|t| t.played).collect();
+        let timing = played.iter().map(|o| o.abs()).sum::<f32>() / played.len().max(1) as f32;
+        format!(
+            "challenge {}/{}: {}/{} notes, {} wrong, {:.0} ms off, {:.0}%",
+            self.level + 1, self.levels.len(), played.len(), self.targets.len(), self.wrong, 1000. * timing, self.score(),
+        )
+    }
+}
+
+#[derive(Component)]
+pub struct ChallengeLabel;
+
+pub fn create_challenge_label(
+    mut commands: Commands,
+    challenge: Res<Challenge>,
+) {
+    let text_style : TextStyle = TextStyle {
+        color: Color::GRAY,
+        font_size: 14.,
+        font: Default::default(),
+    };
+
+    let label = Text2dBundle {
+        text: Text::from_section(challenge.label(), text_style),
+        transform: Transform::from_translation(LABEL_OFFSET),
+        ..default()
+    };
+
+    commands.spawn((label, ChallengeLabel));
+}
+
+// Delete starts the level, or stops it.
+// The levels are the songs of the directory given with `--levels`.
+// Only the notes of the player are scored: the level waits while something else plays notes.
+#[allow(clippy::too_many_arguments)]
+pub fn challenge_system(
+    time: Res<Time>,
+    keyboard_input: Res<Input<KeyCode>>,
+    base_note: Res<BaseNote>,
+    command_line: Res<CommandLine>,
+    entry: Res<TextEntry>,
+    playback: Res<Playback>,
+    sequencer: Res<Sequencer>,
+    arpeggiator: Res<Arpeggiator>,
+    looper: Res<Looper>,
+    quiz: Res<Quiz>,
+    mut note_events: EventReader<NoteEvent>,
+    mut challenge: ResMut<Challenge>,
+    mut label: Query<&mut Text, With<ChallengeLabel>>,
+) {
+    let c = &mut *challenge;
+
+    c.paused = [
+        (playback.playing(), "the song"),
+        (sequencer.playing(), "the sequencer"),
+        (arpeggiator.enabled(), "the arpeggiator"),
+        (looper.playing(), "the looper"),
+        (quiz.active(), "the quiz"),
+    ].into_iter().find(|s| s.0).map(|s| s.1);
+
+    if keyboard_input.just_pressed(KeyCode::Delete) && !entry.active() {
+        if c.running {
+            c.running = false;
+            c.message = Some(format!("{} stopped", c.name));
+        }
+        else if let Some(other) = c.paused {
+            c.message = Some(format!("stop {} to start", other));
+        }
+        else {
+            let dir = command_line.option("levels").unwrap_or(DEFAULT_LEVELS);
+            c.start(Path::new(dir));
+        }
+    }
+
+    if !c.running || c.paused.is_some() {
+        note_events.clear();
+    }
+    else {
+        c.time += time.delta_seconds();
+        for event in note_events.read().filter(|e| e.on) {
+            c.play(event.position.0, base_note.0);
+        }
+        if c.time > c.end() + WINDOW {
+            c.finish();
+        }
+    }
+
+    let value = c.label();
+    for mut text in &mut label {
+        if text.sections[0].value != value {
+            text.sections[0].value = value.clone();
+        }
+    }
+}
+
+// The notes of the level are ghosted dots, a ring closing on each one until it has to be played.
+// They turn green when played, and red when missed.
+pub fn draw_challenge(
+    mut gizmos: Gizmos,
+    base_note: Res<BaseNote>,
+    challenge: Res<Challenge>,
+) {
+    if !challenge.running {
+        return
+    }
+
+    let time = challenge.time;
+    for target in &challenge.targets {
+        if time < target.start - LOOK_AHEAD || time > target.end {
+            continue
+        }
+
+        let position = NotePosition(circle_position(target.pitch, base_note.0));
+        let center = dot_position(&position);
+        let color = match target.played {
+            Some(_) => Color::GREEN,
+            None if time > target.start + WINDOW => Color::RED,
+            None => position.note(base_note.0).color().with_a(0.5),
+        };
+
+        gizmos.circle_2d(center, DOT_RADIUS, color);
+        if time < target.start {
+            let closing = (target.start - time) / LOOK_AHEAD;
+            gizmos.circle_2d(center, DOT_RADIUS * (1. + 2. * closing), color.with_a(0.3));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a running level of notes given by their start and pitch, played with the base note at 0
+    fn challenge(notes: &[(f32, u8)]) -> Challenge {
+        let targets = notes.iter()
+            .map(|&(start, pitch)| Target {start, end: start + 0.5, pitch, played: None})
+            .collect();
+        Challenge {targets, running: true, ..default()}
+    }
+
+    fn played(c: &Challenge) -> Vec<Option<f32>> {
+        c.targets.iter().map(|t| t.played.map(|o| (o * 100.).round() / 100.)).collect()
+    }
+
+    #[test]
+    fn points_are_lost_with_the_offset() {
+        let target = |played| Target {start: 0., end: 1., pitch: 45, played};
+        assert_eq!(target(Some(0.)).points(), NOTE_POINTS);
+        assert_eq!(target(Some(WINDOW)).points(), NOTE_POINTS / 2.);
+        assert_eq!(target(Some(-WINDOW)).points(), NOTE_POINTS / 2.);
+        assert_eq!(target(Some(WINDOW / 2.)).points(), target(Some(-WINDOW / 2.)).points());
+        assert_eq!(target(None).points(), 0.);
+    }
+
+    #[test]
+    fn closest_note_not_played_counts() {
+        let mut c = challenge(&[(1., 45), (1.2, 45)]);
+        c.time = 1.15;
+        c.play(12, 0);
+        assert_eq!(played(&c), vec![None, Some(-0.05)]);
+        c.play(12, 0);
+        assert_eq!(played(&c), vec![Some(0.15), Some(-0.05)]);
+        c.play(12, 0);
+        assert_eq!(c.wrong, 1);
+    }
+
+    #[test]
+    fn notes_out_of_the_window_or_position_are_wrong() {
+        let mut c = challenge(&[(1., 45)]);
+        c.time = 1. - WINDOW - 0.01;
+        c.play(12, 0);
+        c.time = 1.;
+        c.play(13, 0);
+        assert_eq!(played(&c), vec![None]);
+        assert_eq!(c.wrong, 2);
+
+        c.time = 1. + WINDOW - 0.01;
+        c.play(12, 0);
+        assert_eq!(played(&c), vec![Some(0.29)]);
+    }
+
+    #[test]
+    fn positions_follow_the_base_note() {
+        let mut c = challenge(&[(0., 45), (1., 45)]);
+        c.play(12, 0);
+        c.time = 1.;
+        c.play(12, 1);
+        assert_eq!(c.wrong, 1);
+        c.play(11, 1);
+        assert_eq!(played(&c), vec![Some(0.), Some(0.)]);
+    }
+
+    #[test]
+    fn octaves_on_the_same_position_are_played_at_once() {
+        // 57 is out of the circle, and moved down an octave on 45
+        let mut c = challenge(&[(0., 45), (0., 57), (0., 40)]);
+        c.play(12, 0);
+        assert_eq!(played(&c), vec![Some(0.), Some(0.), None]);
+        assert_eq!(c.wrong, 0);
+    }
+
+    #[test]
+    fn wrong_notes_lower_the_score() {
+        let mut c = challenge(&[]);
+        assert_eq!(c.score(), 0.);
+
+        c = challenge(&[(0., 45), (1., 40)]);
+        c.play(12, 0);
+        assert_eq!(c.score(), 50.);
+        c.play(7, 0);
+        assert_eq!(c.score(), 50. - 100. * WRONG_PENALTY / (2. * NOTE_POINTS));
+        c.wrong = 10;
+        assert_eq!(c.score(), 0.);
+
+        c = challenge(&[(0., 45), (0., 40)]);
+        c.play(12, 0);
+        c.play(7, 0);
+        assert_eq!(c.score(), 100.);
+    }
+
+    #[test]
+    fn levels_start_again_from_the_first_when_they_change() {
+        let dir = std::env::temp_dir().join(format!("note-circle-levels-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let mut c = Challenge {level: 3, ..default()};
+        c.start(&dir);
+        assert!(!c.running);
+        assert_eq!(c.level, 0);
+
+        std::fs::write(dir.join("1.abc"), "X:1\nT:first\nK:C\nA,2 E,2|\n").unwrap();
+        std::fs::write(dir.join("notes.txt"), "not a level").unwrap();
+        c.level = 3;
+        c.start(&dir);
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert!(c.running, "{:?}", c.message);
+        assert_eq!((c.level, c.levels.len()), (0, 1));
+        assert_eq!(c.targets.iter().map(|t| t.pitch).collect::<Vec<_>>(), vec![57, 52]);
+    }
+}
@@ -0,0 +1,84 @@
+use bevy::prelude::*;
+
+use super::{PressNote, BaseNote, Latched, CommandLine};
+use super::chords::parse_chord_symbol;
+use super::text_input::{TextEntry, TextTarget, TextSubmitted};
+
+// The chord being typed is played as soon as it can be read.
+// Once validated, it stays latched until the latched notes are cleared.
+#[derive(Default)]
+pub struct ChordInput {
+    text: String,
+    sounding: Vec<usize>,
+}
+
+impl ChordInput {
+    fn release(&mut self) -> Vec<PressNote> {
+        self.sounding.drain(..).map(|position| PressNote {position, pressed: false}).collect()
+    }
+}
+
+// the positions of a chord symbol, given the base note
+fn chord_positions(text: &str, base_note: usize) -> Option<Vec<usize>> {
+    parse_chord_symbol(text.trim()).map(|chord| chord.positions(base_note))
+}
+
+pub fn chord_input_system(
+    keyboard_input: Res<Input<KeyCode>>,
+    base_note: Res<BaseNote>,
+    mut entry: ResMut<TextEntry>,
+    mut submitted: EventReader<TextSubmitted>,
+    mut latched: ResMut<Latched>,
+    mut input: Local<ChordInput>,
+    mut presses: EventWriter<PressNote>,
+) {
+    if keyboard_input.just_pressed(KeyCode::Grave) && !entry.active() {
+        entry.open(TextTarget::Chord);
+    }
+
+    match entry.editing(TextTarget::Chord) {
+        Some(text) if text != input.text => {
+            input.text = text.to_string();
+            presses.send_batch(input.release());
+            if let Some(positions) = chord_positions(text, base_note.0) {
+                presses.send_batch(positions.iter().map(|&position| PressNote {position, pressed: true}));
+                input.sounding = positions;
+            }
+        }
+        Some(_) => {}
+        // validated or cancelled
+        None => {
+            input.text.clear();
+            presses.send_batch(input.release());
+        }
+    }
+
+    for text in submitted.read().filter(|t| t.target == TextTarget::Chord) {
+        if let Some(positions) = chord_positions(&text.text, base_note.0) {
+            presses.send_batch(positions.into_iter().filter_map(|p| latched.chord.latch(p)));
+        }
+    }
+}
+
+// the chord given with `--chord` on the command line
+pub fn chord_from_args(
+    command_line: Res<CommandLine>,
+    base_note: Res<BaseNote>,
+    mut latched: ResMut<Latched>,
+    mut presses: EventWriter<PressNote>,
+) {
+    let text = match command_line.option("chord") {
+        Some(t) => t,
+        None => return
+    };
+
+    let positions = match chord_positions(text, base_note.0) {
+        Some(p) => p,
+        None => {
+            warn!("unknown chord \"{}\"", text);
+            return
+        }
+    };
+
+    presses.send_batch(positions.into_iter().filter_map(|p| latched.chord.latch(p)));
+}
@@ -0,0 +1,275 @@
+use super::N_OCTAVES;
+
+// a kind of chord, given by the half tones between its root and its notes
+pub struct ChordType {
+    pub name: &'static str,
+    // what is written after the root in a chord symbol
+    pub symbol: &'static str,
+    pub intervals: &'static [usize],
+}
+
+pub static CHORD_TYPES: [ChordType; 11] = [
+    ChordType {name: "major", symbol: "", intervals: &[0, 4, 7]},
+    ChordType {name: "minor", symbol: "m", intervals: &[0, 3, 7]},
+    ChordType {name: "dominant seventh", symbol: "7", intervals: &[0, 4, 7, 10]},
+    ChordType {name: "major seventh", symbol: "maj7", intervals: &[0, 4, 7, 11]},
+    ChordType {name: "minor seventh", symbol: "m7", intervals: &[0, 3, 7, 10]},
+    ChordType {name: "diminished", symbol: "dim", intervals: &[0, 3, 6]},
+    ChordType {name: "augmented", symbol: "aug", intervals: &[0, 4, 8]},
+    ChordType {name: "suspended fourth", symbol: "sus4", intervals: &[0, 5, 7]},
+    ChordType {name: "suspended second", symbol: "sus2", intervals: &[0, 2, 7]},
+    ChordType {name: "half-diminished", symbol: "m7b5", intervals: &[0, 3, 6, 10]},
+    ChordType {name: "diminished seventh", symbol: "dim7", intervals: &[0, 3, 6, 9]},
+];
+
+impl ChordType {
+    // The positions to play for this chord, the root being in half tones from the base note.
+    // The root is played in the lowest octave, the other notes above it,
+    // and the root is doubled in the highest octave.
+    pub fn voicing(&self, root: usize) -> Vec<usize> {
+        let root = root % 12;
+        let mut positions: Vec<usize> = self.intervals.iter().map(|i| (root + i) % (12 * N_OCTAVES)).collect();
+
+        let top = root + 12 * (N_OCTAVES - 1);
+        if !positions.contains(&top) {
+            positions.push(top);
+        }
+
+        positions
+    }
+}
+
+// other ways to write the chord types, with the index in `CHORD_TYPES`
+static ALIASES: [(&str, usize); 14] = [
+    ("min", 1),
+    ("-", 1),
+    ("M7", 3),
+    ("Δ7", 3),
+    ("Δ", 3),
+    ("-7", 4),
+    ("o", 5),
+    ("°", 5),
+    ("+", 6),
+    ("sus", 7),
+    ("ø", 9),
+    ("ø7", 9),
+    ("o7", 10),
+    ("°7", 10),
+];
+
+// half tones from A of each letter, and of each name of `NOTE_NAMES` without accidentals
+static LETTERS: [(&str, usize); 14] = [
+    ("sol", 10), ("la", 0), ("si", 2), ("do", 3), ("re", 5), ("mi", 7), ("fa", 8),
+    ("A", 0), ("B", 2), ("C", 3), ("D", 5), ("E", 7), ("F", 8), ("G", 10),
+];
+
+static MAJOR_SCALE: [usize; 7] = [0, 2, 4, 5, 7, 9, 11];
+static NUMERALS: [(&str, usize); 7] = [
+    ("vii", 6), ("vi", 5), ("v", 4), ("iv", 3), ("iii", 2), ("ii", 1), ("i", 0),
+];
+
+// A chord, the notes being in half tones from A
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Chord {
+    pub root: usize,
+    pub chord_type: usize,
+    pub bass: Option<usize>,
+}
+
+impl Chord {
+    // the positions to play, given the base note of the circle
+    pub fn positions(&self, base_note: usize) -> Vec<usize> {
+        let relative = |note: usize| (note + 12 - base_note % 12) % 12;
+        let root = relative(self.root);
+
+        match self.bass {
+            None => CHORD_TYPES[self.chord_type].voicing(root),
+            // the other notes are just above the bass
+            Some(bass) => {
+                let bass = relative(bass);
+                let mut positions = vec![bass];
+                for i in CHORD_TYPES[self.chord_type].intervals {
+                    let above = (root + i + 12 - bass) % 12;
+                    if above != 0 {
+                        positions.push(bass + above);
+                    }
+                }
+                positions
+            }
+        }
+    }
+
+    pub fn symbol(&self, note_names: &[&str; 12]) -> String {
+        let mut symbol = format!("{}{}", note_names[self.root], CHORD_TYPES[self.chord_type].symbol);
+        if let Some(bass) = self.bass {
+            symbol += &format!("/{}", note_names[bass]);
+        }
+        symbol
+    }
+}
+
+// a note name followed by sharps and flats, and the rest of the text
+fn parse_note(text: &str) -> Option<(usize, &str)> {
+    let (mut note, mut rest) = LETTERS.iter()
+        .find_map(|(name, n)| text.strip_prefix(name).map(|rest| (*n, rest)))?;
+
+    loop {
+        if let Some(r) = rest.strip_prefix('#') {
+            note += 1;
+            rest = r;
+        }
+        // a "b" alone is a flat, but "b5" is part of "m7b5"
+        else if let Some(r) = rest.strip_prefix('b').filter(|r| !r.starts_with('5')) {
+            note += 11;
+            rest = r;
+        }
+        else {
+            return Some((note % 12, rest))
+        }
+    }
+}
+
+pub fn parse_chord_type(text: &str) -> Option<usize> {
+    CHORD_TYPES.iter().position(|t| t.symbol == text)
+        .or_else(|| ALIASES.iter().find(|(alias, _)| *alias == text).map(|(_, t)| *t))
+}
+
+// a chord symbol like "Cmaj7/G", "F#m7b5" or "rem"
+pub fn parse_chord_symbol(text: &str) -> Option<Chord> {
+    let (root, rest) = parse_note(text)?;
+
+    let (quality, bass) = match rest.split_once('/') {
+        Some((quality, bass)) => match parse_note(bass)? {
+            (bass, "") => (quality, Some(bass)),
+            _ => return None,
+        },
+        None => (rest, None),
+    };
+
+    Some(Chord {root, chord_type: parse_chord_type(quality)?, bass})
+}
+
+// a roman numeral like "IV", "vi", "V7" or "bVII", in the major key of `tonic`
+pub fn parse_roman_numeral(text: &str, tonic: usize) -> Option<Chord> {
+    let (shift, text) = match text.chars().next()? {
+        '#' => (1, &text[1..]),
+        'b' => (11, &text[1..]),
+        _ => (0, text),
+    };
+
+    // matched on the text itself, as changing its case could change its byte length
+    let (numeral, degree) = NUMERALS.iter()
+        .find(|(n, _)| text.get(..n.len()).is_some_and(|prefix| prefix.eq_ignore_ascii_case(n)))?;
+    let major = text.starts_with(|c: char| c.is_uppercase());
+    let rest = &text[numeral.len()..];
+
+    let chord_type = match (major, rest) {
+        (true, "") => 0,
+        (false, "") => 1,
+        (true, "7") => 2,
+        (false, "7") => 4,
+        (_, quality) => parse_chord_type(quality)?,
+    };
+
+    let root = (tonic + MAJOR_SCALE[*degree] + shift) % 12;
+    Some(Chord {root, chord_type, bass: None})
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn voicing_doubles_the_root_an_octave_up() {
+        // C major over A: C E G, and C again an octave up
+        assert_eq!(CHORD_TYPES[0].voicing(3), vec![3, 7, 10, 15]);
+        // the root is taken in the lowest octave
+        assert_eq!(CHORD_TYPES[0].voicing(15), vec![3, 7, 10, 15]);
+        assert_eq!(CHORD_TYPES[10].voicing(0), vec![0, 3, 6, 9, 12]);
+        assert_eq!(CHORD_TYPES[2].voicing(11), vec![11, 15, 18, 21, 23]);
+    }
+
+    #[test]
+    fn voicing_stays_on_the_circle() {
+        for chord_type in &CHORD_TYPES {
+            for root in 0..12 {
+                assert!(chord_type.voicing(root).iter().all(|&p| p < 12 * N_OCTAVES));
+            }
+        }
+    }
+
+    fn chord(root: usize, chord_type: usize, bass: Option<usize>) -> Option<Chord> {
+        Some(Chord {root, chord_type, bass})
+    }
+
+    #[test]
+    fn chord_symbols() {
+        assert_eq!(parse_chord_symbol("C"), chord(3, 0, None));
+        assert_eq!(parse_chord_symbol("Cmaj7/G"), chord(3, 3, Some(10)));
+        assert_eq!(parse_chord_symbol("F#m7b5"), chord(9, 9, None));
+        // a "b" after the letter is a flat
+        assert_eq!(parse_chord_symbol("Bbm7b5"), chord(1, 9, None));
+        assert_eq!(parse_chord_symbol("Ab7/Eb"), chord(11, 2, Some(6)));
+    }
+
+    #[test]
+    fn chord_symbol_aliases() {
+        assert_eq!(parse_chord_symbol("Cmin"), chord(3, 1, None));
+        assert_eq!(parse_chord_symbol("C-7"), chord(3, 4, None));
+        assert_eq!(parse_chord_symbol("CM7"), chord(3, 3, None));
+        assert_eq!(parse_chord_symbol("CΔ"), chord(3, 3, None));
+        assert_eq!(parse_chord_symbol("C°"), chord(3, 5, None));
+        assert_eq!(parse_chord_symbol("C+"), chord(3, 6, None));
+        assert_eq!(parse_chord_symbol("Csus"), chord(3, 7, None));
+        assert_eq!(parse_chord_symbol("Cø"), chord(3, 9, None));
+        assert_eq!(parse_chord_symbol("Co7"), chord(3, 10, None));
+    }
+
+    #[test]
+    fn solfege_chord_symbols() {
+        assert_eq!(parse_chord_symbol("rem"), chord(5, 1, None));
+        assert_eq!(parse_chord_symbol("sol7"), chord(10, 2, None));
+        assert_eq!(parse_chord_symbol("fa#"), chord(9, 0, None));
+        assert_eq!(parse_chord_symbol("sib"), chord(1, 0, None));
+        assert_eq!(parse_chord_symbol("do/mi"), chord(3, 0, Some(7)));
+    }
+
+    #[test]
+    fn wrong_chord_symbols() {
+        for text in ["", "H", "Cxyz", "C/G7", "C/", "é", "Cé"] {
+            assert_eq!(parse_chord_symbol(text), None, "{}", text);
+        }
+    }
+
+    #[test]
+    fn slash_chord_is_voiced_above_its_bass() {
+        let positions = parse_chord_symbol("Cmaj7/G").unwrap().positions(0);
+        assert_eq!(positions, vec![10, 15, 19, 14]);
+        // the bass moves with the base note
+        let positions = parse_chord_symbol("Cmaj7/G").unwrap().positions(10);
+        assert_eq!(positions, vec![0, 5, 9, 4]);
+    }
+
+    #[test]
+    fn roman_numerals_in_c() {
+        let c = 3;
+        assert_eq!(parse_roman_numeral("I", c), chord(3, 0, None));
+        assert_eq!(parse_roman_numeral("IV", c), chord(8, 0, None));
+        assert_eq!(parse_roman_numeral("V7", c), chord(10, 2, None));
+        assert_eq!(parse_roman_numeral("bVII", c), chord(1, 0, None));
+        // lower case numerals are minor chords
+        assert_eq!(parse_roman_numeral("vi", c), chord(0, 1, None));
+        assert_eq!(parse_roman_numeral("ii7", c), chord(5, 4, None));
+        assert_eq!(parse_roman_numeral("iii", c), chord(7, 1, None));
+        assert_eq!(parse_roman_numeral("viio", c), chord(2, 5, None));
+        assert_eq!(parse_roman_numeral("#iv", c), chord(9, 1, None));
+    }
+
+    #[test]
+    fn non_ascii_roman_numerals() {
+        for text in ["é", "bé", "#", "ⅳ", "Ⅳ", "iï", "vé"] {
+            assert_eq!(parse_roman_numeral(text, 3), None, "{}", text);
+        }
+        assert_eq!(parse_roman_numeral("Iø", 3), chord(3, 9, None));
+    }
+}
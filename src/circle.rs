@@ -3,7 +3,7 @@ use bevy::prelude::*;
 use bevy::sprite::MaterialMesh2dBundle;
 use bevy::render::render_resource::PrimitiveTopology;
 
-use super::{NotePosition, Playing, BaseNote, Angle, N_OCTAVES};
+use super::{NotePosition, Playing, BaseNote, Angle, HideNotes, N_OCTAVES};
 
 use std::f32::consts::PI;
 
@@ -11,6 +11,7 @@ const OUTER_CIRCLE_RAD: f32 = 180.;
 const NOTE_NAME_CIRCLE_RAD: f32 = 200.;
 const INNER_CIRCLE_RAD: f32 = 100.;
 const OFFSET: Vec3 = Vec3::new(-300., 0., -1.);
+pub const DOT_RADIUS: f32 = 8.;
 
 #[derive(Component)]
 pub struct Background;
@@ -27,6 +28,24 @@ fn polar3(angle: f32, radius: f32, z: f32) -> Vec3 {
     polar2(angle, radius).extend(z)
 }
 
+// distance from the center of the circle where a note is drawn
+fn note_radius(p: &NotePosition) -> f32 {
+    let range = p.0 as f32 / (12. * N_OCTAVES as f32);
+    INNER_CIRCLE_RAD + (OUTER_CIRCLE_RAD - INNER_CIRCLE_RAD) * range
+}
+
+pub fn dot_position(p: &NotePosition) -> Vec2 {
+    polar2(p.angle().0, note_radius(p)) + OFFSET.truncate()
+}
+
+// the note whose dot is under this point, even if the dot is hidden
+pub fn note_at(point: Vec2) -> Option<usize> {
+    (0..12 * N_OCTAVES)
+        .map(NotePosition)
+        .find(|p| dot_position(p).distance(point) <= DOT_RADIUS)
+        .map(|p| p.0)
+}
+
 pub fn create_note_names(
     mut commands: Commands, 
     base_note: Res<BaseNote>,
@@ -80,8 +99,7 @@ pub fn create_circle(
 
     for (e, p, angle) in &positions {
 
-        let range = p.0 as f32 / (12. * N_OCTAVES as f32);
-        let rad = INNER_CIRCLE_RAD + (OUTER_CIRCLE_RAD - INNER_CIRCLE_RAD) * range;
+        let rad = note_radius(p);
 
         let points = vec![
                 OFFSET + polar3(angle.0 - 0.060 * PI, rad, -1.),
@@ -97,11 +115,11 @@ pub fn create_circle(
             ..default()
         };
 
-        commands.spawn((line, angle.clone(), Background));
+        commands.spawn((line, *angle, Background));
 
         let color = p.note(0).color();
         let circle = MaterialMesh2dBundle {
-            mesh: meshes.add(shape::Circle::new(8.).into()).into(),
+            mesh: meshes.add(shape::Circle::new(DOT_RADIUS).into()).into(),
             material: materials.add(color.into()),
             transform: Transform::from_translation(polar3(angle.0, rad, 0.5) + OFFSET),
             ..default()
@@ -112,11 +130,13 @@ pub fn create_circle(
 
 }
 
+#[allow(clippy::type_complexity)]
 pub fn draw_notes(
+    hide: Res<HideNotes>,
     mut notes: Query<(&mut Visibility, &Playing), (With<NotePosition>,  Without<Background>)>,
     ) {
     for (mut visible, playing) in notes.iter_mut() {
-        if playing.0 {
+        if playing.0 && !hide.0 {
             *visible = Visibility::Visible;
         }
         else {
@@ -0,0 +1,165 @@
+use bevy::prelude::*;
+
+use std::collections::VecDeque;
+
+use super::{MainString, STRING_LENGTH};
+use super::string::{StringState, StringParams};
+use super::spectrum::spectrum;
+
+// bottom left corner of the graph
+const OFFSET: Vec2 = Vec2::new(100., -230.);
+const WIDTH: f32 = 400.;
+const HEIGHT: f32 = 60.;
+
+// number of recorded frames, must be a power of 2 for the FFT
+const HISTORY: usize = 512;
+// where the displacement is measured, as a fraction of the string
+const PROBE_POSITION: f32 = 0.1;
+// the spectrum is drawn in log scale, between 10^-DECADES and 1
+const DECADES: f32 = 4.;
+
+#[derive(Resource)]
+pub struct ShowDiagnostics(bool);
+
+#[derive(Component)]
+pub struct DiagnosticsText;
+
+// what is recorded from the string, once per frame
+#[derive(Component, Default)]
+pub struct StringProbe {
+    displacement: VecDeque<f32>,
+    energy: VecDeque<f32>,
+}
+
+impl StringProbe {
+    fn record(&mut self, displacement: f32, energy: f32) {
+        self.displacement.push_back(displacement);
+        self.energy.push_back(energy);
+        if self.displacement.len() > HISTORY {
+            self.displacement.pop_front();
+            self.energy.pop_front();
+        }
+    }
+}
+
+pub fn create_diagnostics(
+    mut commands: Commands,
+) {
+    commands.insert_resource(ShowDiagnostics(false));
+
+    let text_style : TextStyle = TextStyle {
+        color: Color::WHITE,
+        font_size: 14.,
+        font: Default::default(),
+    };
+
+    let text = Text2dBundle {
+        text: Text::from_section("", text_style),
+        transform: Transform::from_translation(
+            (OFFSET + Vec2::new(WIDTH / 2., HEIGHT + 12.)).extend(0.)
+        ),
+        visibility: Visibility::Hidden,
+        ..default()
+    };
+
+    commands.spawn((text, DiagnosticsText));
+}
+
+pub fn toggle_diagnostics(
+    keyboard_input: Res<Input<KeyCode>>,
+    mut show: ResMut<ShowDiagnostics>,
+    mut text: Query<&mut Visibility, With<DiagnosticsText>>,
+) {
+    if !keyboard_input.just_pressed(KeyCode::F1) {
+        return
+    }
+
+    show.0 = !show.0;
+    for mut visible in &mut text {
+        *visible = if show.0 {Visibility::Visible} else {Visibility::Hidden};
+    }
+}
+
+pub fn hide_diagnostics(
+    mut show: ResMut<ShowDiagnostics>,
+    mut text: Query<&mut Visibility, With<DiagnosticsText>>,
+) {
+    show.0 = false;
+    for mut visible in &mut text {
+        *visible = Visibility::Hidden;
+    }
+}
+
+pub fn record_probe(
+    mut string: Query<(&StringState, &StringParams, &mut StringProbe), With<MainString>>,
+) {
+    if let Ok((s, p, mut probe)) = string.get_single_mut() {
+        let (kinetic, potential) = s.energy(p);
+        probe.record(s.displacement(PROBE_POSITION), kinetic + potential);
+    }
+}
+
+pub fn draw_diagnostics(
+    mut gizmos: Gizmos,
+    show: Res<ShowDiagnostics>,
+    string: Query<(&StringState, &StringParams, &StringProbe), With<MainString>>,
+    mut text: Query<&mut Text, With<DiagnosticsText>>,
+) {
+    if !show.0 {
+        return
+    }
+
+    let (s, p, probe) = match string.get_single() {
+        Ok(a) => a,
+        Err(_) => return
+    };
+
+    let (kinetic, potential) = s.energy(p);
+    for mut text in &mut text {
+        text.sections[0].value = format!("kinetic {:.2e}   potential {:.2e}", kinetic, potential);
+    }
+
+    gizmos.rect_2d(OFFSET + Vec2::new(WIDTH, HEIGHT) / 2., 0., Vec2::new(WIDTH, HEIGHT), Color::DARK_GRAY);
+
+    // total energy over time, relative to its maximum
+    let max_energy = probe.energy.iter().fold(f32::EPSILON, |m, &e| m.max(e));
+    gizmos.linestrip_2d(
+        probe.energy.iter().enumerate().map(|(i, e)| OFFSET + Vec2::new(
+            i as f32 / HISTORY as f32 * WIDTH,
+            e / max_energy * HEIGHT,
+        )),
+        Color::GRAY,
+    );
+
+    if probe.displacement.len() < HISTORY {
+        return
+    }
+
+    let signal: Vec<f32> = probe.displacement.iter().copied().collect();
+    let magnitudes = spectrum(&signal);
+    let n_bins = magnitudes.len() as f32;
+
+    gizmos.linestrip_2d(
+        magnitudes.iter().enumerate().map(|(k, m)| OFFSET + Vec2::new(
+            k as f32 / n_bins * WIDTH,
+            ((1. + m.max(f32::MIN_POSITIVE).log10() / DECADES) * HEIGHT).clamp(0., HEIGHT),
+        )),
+        Color::CYAN,
+    );
+
+    // where the junctions should resonate: the fundamental and the first
+    // harmonics of each note, with one sample per frame
+    let sample_period = p.dt * p.steps_per_render as f32;
+    for note in &p.chord {
+        let f = 0.5 * p.c / (note.relative_length() * STRING_LENGTH);
+        for harmonic in 1..=4 {
+            let bin = harmonic as f32 * f * HISTORY as f32 * sample_period;
+            if bin >= n_bins {
+                break
+            }
+            let x = bin / n_bins * WIDTH;
+            let top = HEIGHT / harmonic as f32;
+            gizmos.line_2d(OFFSET + Vec2::new(x, 0.), OFFSET + Vec2::new(x, top), note.color());
+        }
+    }
+}
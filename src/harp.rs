@@ -0,0 +1,101 @@
+use bevy::prelude::*;
+
+use super::{NotePosition, Playing, MainString, VibratingString, HideNotes, STRING_LENGTH, N};
+use super::string::{StringState, StringParams};
+
+const OFFSET: Vec2 = Vec2::new(300., -220.);
+const HARP_WIDTH: f32 = 500.;
+const HARP_HEIGHT: f32 = 420.;
+
+// one vibrating string for each note being played
+#[derive(Component)]
+pub struct HarpString(NotePosition);
+
+// spawn and despawn the strings so that they match the notes being played
+pub fn change_harp(
+    mut commands: Commands,
+    notes: Query<(&NotePosition, &Playing)>,
+    harp: Query<(Entity, &HarpString)>,
+    main_string: Query<&StringParams, With<MainString>>,
+) {
+    let playing: Vec<_> = notes.iter().filter(|(_, p)| p.0).map(|(x, _)| x).collect();
+
+    for (e, string) in &harp {
+        if !playing.iter().any(|n| n.0 == string.0.0) {
+            commands.entity(e).despawn();
+        }
+    }
+
+    // the new strings are made of the same material as the main one
+    let material = main_string.get_single().cloned().unwrap_or_default();
+
+    for note_position in playing {
+        if harp.iter().any(|(_, s)| s.0.0 == note_position.0) {
+            continue
+        }
+
+        let note = note_position.note(0);
+        let r = note.relative_length();
+        let n_samples = (N as f32 * r) as usize;
+
+        let params = StringParams {
+            length: r * STRING_LENGTH,
+            n_samples,
+            chord: vec![note],
+            ..material.clone()
+        };
+
+        commands.spawn((
+            VibratingString {
+                params,
+                state: StringState::new_flat(n_samples),
+            },
+            HarpString(note_position.clone()),
+        ));
+    }
+}
+
+pub fn update_harp(
+    mut harp: Query<(&mut StringState, &StringParams), With<HarpString>>,
+) {
+    for (mut s, params) in &mut harp {
+        for _ in 0..params.steps_per_render {
+            s.step(params);
+        }
+    }
+}
+
+pub fn draw_harp(
+    mut gizmos: Gizmos,
+    hide: Res<HideNotes>,
+    harp: Query<(&StringState, &StringParams, &HarpString)>,
+) {
+    // the length of each string is its note
+    if hide.0 {
+        return
+    }
+
+    let mut strings: Vec<_> = harp.iter().collect();
+    strings.sort_by_key(|(_, _, h)| h.0.0);
+
+    let spacing = HARP_WIDTH / strings.len() as f32;
+    let scale = HARP_HEIGHT / STRING_LENGTH;
+
+    for (i, (s, p, h)) in strings.into_iter().enumerate() {
+        let origin = OFFSET + Vec2::new(-HARP_WIDTH/2. + (i as f32 + 0.5) * spacing, 0.);
+        s.draw_along(p, origin, Vec2::Y, scale, &mut gizmos);
+
+        let color = h.0.note(0).color();
+        gizmos.circle_2d(origin, 5., color);
+        gizmos.circle_2d(origin + Vec2::Y * p.length * scale, 5., color);
+    }
+}
+
+pub fn clear_harp(
+    mut commands: Commands,
+    harp: Query<Entity, With<HarpString>>,
+) {
+    for e in &harp {
+        commands.entity(e).despawn();
+    }
+}
@@ -0,0 +1,116 @@
+use bevy::prelude::*;
+
+use super::{NotePosition, Playing, BaseNote, HideNotes};
+
+use std::f32::consts::PI;
+
+// inside the circle
+const OFFSET: Vec2 = Vec2::new(-300., 0.);
+const RADIUS: f32 = 70.;
+const LABEL_OFFSET: Vec3 = Vec3::new(-300., -235., 0.);
+
+const N_POINTS: usize = 600;
+// number of periods of the lowest note drawn
+const N_PERIODS: f32 = 8.;
+// the figure moves as if the time was going this much slower
+const SLOW_DOWN: f32 = 0.005;
+
+static INTERVAL_NAMES: [&str; 12] = [
+    "unison", "minor second", "major second", "minor third", "major third", "perfect fourth",
+    "tritone", "perfect fifth", "minor sixth", "major sixth", "minor seventh", "major seventh",
+];
+
+// the simplest ratios close to each interval of the equal temperament
+static JUST_RATIOS: [(u32, u32); 12] = [
+    (1, 1), (16, 15), (9, 8), (6, 5), (5, 4), (4, 3),
+    (45, 32), (3, 2), (8, 5), (5, 3), (9, 5), (15, 8),
+];
+
+#[derive(Component)]
+pub struct IntervalLabel;
+
+// name of an interval given by its number of half tones
+pub fn interval_name(half_tones: usize) -> String {
+    match (half_tones / 12, half_tones % 12) {
+        (0, i) => INTERVAL_NAMES[i].to_string(),
+        (1, 0) => "octave".to_string(),
+        (octaves, 0) => format!("{} octaves", octaves),
+        (1, i) => format!("octave + {}", INTERVAL_NAMES[i]),
+        (octaves, i) => format!("{} octaves + {}", octaves, INTERVAL_NAMES[i]),
+    }
+}
+
+fn just_ratio(half_tones: usize) -> (u32, u32) {
+    let (num, den) = JUST_RATIOS[half_tones % 12];
+    (num << (half_tones / 12), den)
+}
+
+// how far the equal temperament is from the just ratio, in cents
+fn cents_deviation(half_tones: usize) -> f32 {
+    let (num, den) = just_ratio(half_tones);
+    100. * half_tones as f32 - 1200. * (num as f32 / den as f32).log2()
+}
+
+pub fn create_interval_label(
+    mut commands: Commands,
+) {
+    let text_style : TextStyle = TextStyle {
+        color: Color::WHITE,
+        font_size: 16.,
+        font: Default::default(),
+    };
+
+    let label = Text2dBundle {
+        text: Text::from_section("", text_style),
+        transform: Transform::from_translation(LABEL_OFFSET),
+        ..default()
+    };
+
+    commands.spawn((label, IntervalLabel));
+}
+
+pub fn draw_interval(
+    mut gizmos: Gizmos,
+    time: Res<Time>,
+    base_note: Res<BaseNote>,
+    hide: Res<HideNotes>,
+    notes: Query<(&NotePosition, &Playing)>,
+    mut label: Query<&mut Text, With<IntervalLabel>>,
+) {
+    let mut playing: Vec<_> = notes.iter().filter(|(_, p)| p.0).map(|(x, _)| x).collect();
+
+    let mut label = match label.get_single_mut() {
+        Ok(l) => l,
+        Err(_) => return
+    };
+
+    if playing.len() != 2 || hide.0 {
+        label.sections[0].value.clear();
+        return
+    }
+
+    playing.sort_by_key(|x| x.0);
+    let half_tones = playing[1].0 - playing[0].0;
+    let (num, den) = just_ratio(half_tones);
+
+    label.sections[0].value = format!(
+        "{}  ~ {}/{}  {:+.1} cents",
+        interval_name(half_tones), num, den, cents_deviation(half_tones)
+    );
+
+    let f1 = playing[0].note(base_note.0).to_freq();
+    let f2 = playing[1].note(base_note.0).to_freq();
+    let t0 = time.elapsed_seconds() * SLOW_DOWN;
+    let duration = N_PERIODS / f1;
+
+    gizmos.linestrip_2d(
+        (0..=N_POINTS).map(|i| {
+            let t = t0 + duration * i as f32 / N_POINTS as f32;
+            OFFSET + RADIUS * Vec2::new(
+                f32::sin(2. * PI * f1 * t),
+                f32::sin(2. * PI * f2 * t),
+            )
+        }),
+        Color::BLACK,
+    );
+}
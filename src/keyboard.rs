@@ -1,6 +1,13 @@
 use bevy::prelude::*;
 
-use super::{Playing, NotePosition, BaseNote, UpdateNoteMapping, ChordJustChanged};
+use bevy::utils::HashSet;
+
+use super::{NotePosition, BaseNote, UpdateNoteMapping, View, PressNote, Latched, NOTE_NAMES};
+use super::chords::CHORD_TYPES;
+use super::text_input::TextEntry;
+use super::quiz::Quiz;
+
+const LABEL_OFFSET: Vec3 = Vec3::new(-300., 250., 0.);
 
 static KEYS: [(KeyCode, usize, usize); 24] = [
     (KeyCode::Key1, 0, 1),
@@ -15,59 +22,231 @@ static KEYS: [(KeyCode, usize, usize); 24] = [
     (KeyCode::Key0, 9, 1),
     (KeyCode::Minus, 10, 1),
     (KeyCode::Equals, 11, 1),
-    (KeyCode::Q, 00, 0),
-    (KeyCode::W, 01, 0),
-    (KeyCode::E, 02, 0),
-    (KeyCode::R, 03, 0),
-    (KeyCode::T, 04, 0),
-    (KeyCode::Y, 05, 0),
-    (KeyCode::U, 06, 0),
-    (KeyCode::I, 07, 0),
-    (KeyCode::O, 08, 0),
-    (KeyCode::P, 09, 0),
+    (KeyCode::Q, 0, 0),
+    (KeyCode::W, 1, 0),
+    (KeyCode::E, 2, 0),
+    (KeyCode::R, 3, 0),
+    (KeyCode::T, 4, 0),
+    (KeyCode::Y, 5, 0),
+    (KeyCode::U, 6, 0),
+    (KeyCode::I, 7, 0),
+    (KeyCode::O, 8, 0),
+    (KeyCode::P, 9, 0),
     (KeyCode::BracketLeft, 10, 0),
     (KeyCode::BracketRight, 11, 0),
 ];
 
+// in chord mode, each of these keys plays a chord type of `CHORD_TYPES`
+pub static CHORD_KEYS: [KeyCode; 11] = [
+    KeyCode::A,
+    KeyCode::S,
+    KeyCode::D,
+    KeyCode::F,
+    KeyCode::G,
+    KeyCode::H,
+    KeyCode::J,
+    KeyCode::K,
+    KeyCode::L,
+    KeyCode::Semicolon,
+    KeyCode::Apostrophe,
+];
+
+// what happens when a note key is pressed
+#[derive(Resource, Default, Clone, Copy, PartialEq, Eq)]
+pub enum KeyboardMode {
+    // the note is played as long as the key is pressed
+    #[default]
+    Hold,
+    // the note is switched on or off, to build chords bigger than what
+    // the keyboard can detect at once
+    Latch,
+    // the note keys choose the root, and the chord keys play a whole chord
+    Chord,
+}
+
+impl KeyboardMode {
+    fn name(self) -> &'static str {
+        match self {
+            KeyboardMode::Hold => "keys: hold",
+            KeyboardMode::Latch => "keys: latch",
+            KeyboardMode::Chord => "keys: chords",
+        }
+    }
+
+    fn next(self) -> Self {
+        match self {
+            KeyboardMode::Hold => KeyboardMode::Latch,
+            KeyboardMode::Latch => KeyboardMode::Chord,
+            KeyboardMode::Chord => KeyboardMode::Hold,
+        }
+    }
+}
+
+// the state of the chord mode
+#[derive(Default)]
+pub struct ChordButtons {
+    root: usize,
+    // the chord key being held with its chord type, and the notes it plays
+    key: Option<(KeyCode, usize)>,
+    playing: Vec<usize>,
+}
+
+impl ChordButtons {
+    fn release(&mut self) -> Vec<PressNote> {
+        self.key = None;
+        self.playing.drain(..).map(|position| PressNote {position, pressed: false}).collect()
+    }
+
+    fn press(&mut self, key: KeyCode, chord_type: usize) -> Vec<PressNote> {
+        let mut presses = self.release();
+        self.key = Some((key, chord_type));
+        self.playing = CHORD_TYPES[chord_type].voicing(self.root);
+        presses.extend(self.playing.iter().map(|&position| PressNote {position, pressed: true}));
+        presses
+    }
+
+    fn label(&self, base_note: usize) -> String {
+        let root = NOTE_NAMES[(self.root + base_note) % 12];
+        match self.key {
+            Some((_, t)) => format!("keys: chords, {}{} ({})", root, CHORD_TYPES[t].symbol, CHORD_TYPES[t].name),
+            None => format!("keys: chords on {}", root),
+        }
+    }
+}
+
+#[derive(Component)]
+pub struct KeyboardModeLabel;
+
+pub fn create_keyboard_mode_label(
+    mut commands: Commands,
+    mode: Res<KeyboardMode>,
+) {
+    let text_style : TextStyle = TextStyle {
+        color: Color::GRAY,
+        font_size: 14.,
+        font: Default::default(),
+    };
+
+    let label = Text2dBundle {
+        text: Text::from_section(mode.name(), text_style),
+        transform: Transform::from_translation(LABEL_OFFSET),
+        ..default()
+    };
+
+    commands.spawn((label, KeyboardModeLabel));
+}
+
+#[allow(clippy::too_many_arguments)]
 pub fn keyboard_input_system(
     mut mapping_changed: EventWriter<UpdateNoteMapping>,
     mut base_note: ResMut<BaseNote>,
     keyboard_input: Res<Input<KeyCode>>, 
-    mut query: Query<(&NotePosition, &AudioSink, &mut Playing)>,
-    mut chord_changed: ResMut<ChordJustChanged>,
+    mut presses: EventWriter<PressNote>,
+    mut mode: ResMut<KeyboardMode>,
+    mut latched: ResMut<Latched>,
+    entry: Res<TextEntry>,
+    quiz: Res<Quiz>,
+    // the notes held by a key in hold mode
+    mut held: Local<HashSet<usize>>,
+    mut chords: Local<ChordButtons>,
+    mut label: Query<&mut Text, With<KeyboardModeLabel>>,
 ) {
+    // the keys are used to type some text, or to answer the quiz
+    if entry.active() || quiz.captures_keys() {
+        presses.send_batch(held.drain().map(|position| PressNote {position, pressed: false}));
+        if chords.key.is_some() {
+            presses.send_batch(chords.release());
+            for mut text in &mut label {
+                text.sections[0].value = chords.label(base_note.0);
+            }
+        }
+        return
+    }
 
-    // TODO: use an event
+    let mut label_changed = false;
 
     if keyboard_input.just_pressed(KeyCode::Right) {
         base_note.0 += 1;
         mapping_changed.send(UpdateNoteMapping);
-        return
+        label_changed = true;
     }
     if keyboard_input.just_pressed(KeyCode::Left) && base_note.0 > 0 {
         base_note.0 -= 1;
         mapping_changed.send(UpdateNoteMapping);
-        return
+        label_changed = true;
     }
 
-    for (note, sink, mut playing) in &mut query {
-        for (k, oclock, height) in KEYS {
-            if note.oclock() == oclock && note.height() == height {
-                if keyboard_input.pressed(k) && !playing.0 {
-                    chord_changed.0 = true;
-                    sink.play()
-                }
-                if !keyboard_input.pressed(k) && playing.0 {
-                    chord_changed.0 = true;
-                }
-                if keyboard_input.pressed(k) {
-                    playing.0 = true;
+    if keyboard_input.just_pressed(KeyCode::F4) {
+        // leaving the latch mode releases its notes
+        if *mode == KeyboardMode::Latch {
+            presses.send_batch(latched.keys.clear());
+        }
+        *mode = mode.next();
+        presses.send_batch(chords.release());
+        label_changed = true;
+    }
+
+    if keyboard_input.just_pressed(KeyCode::Back) {
+        presses.send_batch(latched.clear());
+    }
+
+    for (k, oclock, height) in KEYS {
+        let position = NotePosition::new(oclock, height).0;
+        if keyboard_input.just_pressed(k) {
+            match *mode {
+                KeyboardMode::Hold => {
+                    held.insert(position);
+                    presses.send(PressNote {position, pressed: true});
                 }
-                if !keyboard_input.pressed(k) {
-                    playing.0 = false;
-                    sink.pause();
+                KeyboardMode::Latch => presses.send(latched.keys.toggle(position)),
+                KeyboardMode::Chord => {
+                    chords.root = oclock;
+                    label_changed = true;
+                    // the chord being held follows the root
+                    if let Some((key, chord_type)) = chords.key {
+                        presses.send_batch(chords.press(key, chord_type));
+                    }
                 }
             }
         }
+        if keyboard_input.just_released(k) && held.remove(&position) {
+            presses.send(PressNote {position, pressed: false});
+        }
+    }
+
+    if *mode == KeyboardMode::Chord {
+        for (chord_type, &key) in CHORD_KEYS.iter().enumerate() {
+            if keyboard_input.just_pressed(key) {
+                presses.send_batch(chords.press(key, chord_type));
+                label_changed = true;
+            }
+            if keyboard_input.just_released(key) && chords.key == Some((key, chord_type)) {
+                presses.send_batch(chords.release());
+                label_changed = true;
+            }
+        }
+    }
+
+    if label_changed {
+        let value = match *mode {
+            KeyboardMode::Chord => chords.label(base_note.0),
+            _ => mode.name().to_string(),
+        };
+        for mut text in &mut label {
+            text.sections[0].value = value.clone();
+        }
+    }
+}
+
+pub fn view_input_system(
+    keyboard_input: Res<Input<KeyCode>>,
+    view: Res<State<View>>,
+    mut next_view: ResMut<NextState<View>>,
+) {
+    if keyboard_input.just_pressed(KeyCode::Up) {
+        next_view.set(view.get().next());
+    }
+    if keyboard_input.just_pressed(KeyCode::Down) {
+        next_view.set(view.get().previous());
     }
 }
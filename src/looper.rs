@@ -0,0 +1,280 @@
+use bevy::prelude::*;
+use bevy::utils::HashSet;
+
+use super::PressNote;
+use super::transport::Transport;
+use super::text_input::TextEntry;
+
+const LABEL_OFFSET: Vec3 = Vec3::new(300., -230., 0.);
+
+static LOOP_BARS: [usize; 4] = [1, 2, 4, 8];
+
+// a recorded phrase, played again at each turn of the loop
+struct Layer {
+    // the position in the loop in beats, the note, and whether it is pressed
+    events: Vec<(f32, usize, bool)>,
+    muted: bool,
+    // the notes of this layer sounding now
+    on: HashSet<usize>,
+}
+
+struct Recording {
+    // the position of the transport when the recording starts
+    start: f32,
+    events: Vec<(f32, usize, bool)>,
+    held: HashSet<usize>,
+}
+
+// Records phrases for a few bars, and plays them in a loop on top of each other
+#[derive(Resource)]
+pub struct Looper {
+    bars: usize,
+    // the length of the loop in beats, fixed by the first layer
+    length: f32,
+    // the position of the transport where the loop starts
+    origin: f32,
+    // how far in the loop the layers have been played
+    played: Option<f32>,
+    layers: Vec<Layer>,
+    recording: Option<Recording>,
+    selected: usize,
+}
+
+impl Default for Looper {
+    fn default() -> Self {
+        Looper {
+            bars: 2,
+            length: 0.,
+            origin: 0.,
+            played: None,
+            layers: Vec::new(),
+            recording: None,
+            selected: 0,
+        }
+    }
+}
+
+impl Looper {
+    // whether some layer can play notes
+    pub fn playing(&self) -> bool {
+        self.layers.iter().any(|l| !l.muted && !l.events.is_empty())
+    }
+
+    // whether a note is played by one of the layers
+    pub fn sounding(&self, position: usize) -> bool {
+        self.layers.iter().any(|l| !l.muted && l.on.contains(&position))
+    }
+
+    // The first recording starts on the next bar, and sets the length of the loop.
+    // The next ones are overdubbed during one turn of the loop from now.
+    fn record(&mut self, transport: &Transport) {
+        let now = transport.position();
+        let start = if self.layers.is_empty() {
+            let bar = transport.beats_per_bar() as f32;
+            self.length = self.bars as f32 * bar;
+            self.origin = (now / bar).ceil() * bar;
+            self.played = None;
+            self.origin
+        }
+        else {
+            now
+        };
+
+        self.recording = Some(Recording {start, events: Vec::new(), held: HashSet::new()});
+    }
+
+    fn finish_recording(&mut self) {
+        let mut recording = match self.recording.take() {
+            Some(r) => r,
+            None => return
+        };
+
+        // the notes still held are released at the end of the loop
+        for &position in &recording.held {
+            recording.events.push((0., position, false));
+        }
+        // and a note released and pressed at the same time is released first
+        recording.events.sort_by(|a, b| a.0.total_cmp(&b.0).then(a.2.cmp(&b.2)));
+
+        self.layers.push(Layer {events: recording.events, muted: false, on: HashSet::new()});
+        self.selected = self.layers.len() - 1;
+    }
+
+    // plays the events of the layers up to `position` in the loop
+    fn play(&mut self, position: f32) {
+        let length = self.length;
+        // nothing has been played yet, the events at the start are included
+        let from = self.played.map_or(-1., |p| p.rem_euclid(length));
+        let to = position.rem_euclid(length);
+        let wrapped = self.played.is_some_and(|p| position - p >= length) || to < from;
+
+        for layer in &mut self.layers {
+            let events = layer.events.iter()
+                .filter(|(t, _, _)| *t > from && (wrapped || *t <= to))
+                .chain(layer.events.iter().filter(|(t, _, _)| wrapped && *t <= to));
+
+            for &(_, p, pressed) in events {
+                if pressed {
+                    layer.on.insert(p);
+                }
+                else {
+                    layer.on.remove(&p);
+                }
+            }
+        }
+
+        self.played = Some(position);
+    }
+
+    fn silence(&mut self) {
+        for layer in &mut self.layers {
+            layer.on.clear();
+        }
+    }
+
+    fn label(&self, transport: &Transport) -> String {
+        let mut text = format!("looper: {} bar(s)", self.bars);
+
+        if !self.layers.is_empty() {
+            text += ", layers:";
+            for (i, layer) in self.layers.iter().enumerate() {
+                let name = if layer.muted {format!("({})", i + 1)} else {(i + 1).to_string()};
+                if i == self.selected {
+                    text += &format!(" [{}]", name);
+                }
+                else {
+                    text += &format!(" {}", name);
+                }
+            }
+        }
+
+        if let Some(r) = &self.recording {
+            let beat = transport.position() - r.start;
+            if beat < 0. {
+                text += ", waiting for the next bar";
+            }
+            else {
+                text += &format!(", recording {}/{}", beat as usize + 1, self.length);
+            }
+        }
+
+        text
+    }
+}
+
+#[derive(Component)]
+pub struct LooperLabel;
+
+pub fn create_looper_label(
+    mut commands: Commands,
+    looper: Res<Looper>,
+    transport: Res<Transport>,
+) {
+    let text_style : TextStyle = TextStyle {
+        color: Color::GRAY,
+        font_size: 14.,
+        font: Default::default(),
+    };
+
+    let label = Text2dBundle {
+        text: Text::from_section(looper.label(&transport), text_style),
+        transform: Transform::from_translation(LABEL_OFFSET),
+        ..default()
+    };
+
+    commands.spawn((label, LooperLabel));
+}
+
+pub fn looper_input_system(
+    keyboard_input: Res<Input<KeyCode>>,
+    entry: Res<TextEntry>,
+    mut transport: ResMut<Transport>,
+    mut looper: ResMut<Looper>,
+) {
+    if entry.active() {
+        return
+    }
+
+    let l = &mut *looper;
+
+    if keyboard_input.just_pressed(KeyCode::Z) {
+        if l.recording.is_some() {
+            l.recording = None;
+        }
+        else {
+            if !transport.playing() {
+                transport.start();
+            }
+            l.record(&transport);
+        }
+    }
+    // undo the last layer, or the recording
+    if keyboard_input.just_pressed(KeyCode::X) && l.recording.take().is_none() {
+        l.layers.pop();
+        l.selected = l.selected.min(l.layers.len().saturating_sub(1));
+    }
+    if keyboard_input.just_pressed(KeyCode::C) && !l.layers.is_empty() {
+        l.selected = (l.selected + 1) % l.layers.len();
+    }
+    if keyboard_input.just_pressed(KeyCode::V) {
+        if let Some(layer) = l.layers.get_mut(l.selected) {
+            layer.muted = !layer.muted;
+        }
+    }
+    // the length can only be changed before the first recording
+    if keyboard_input.just_pressed(KeyCode::N) && l.layers.is_empty() && l.recording.is_none() {
+        let i = LOOP_BARS.iter().position(|&b| b == l.bars).unwrap_or(0);
+        l.bars = LOOP_BARS[(i + 1) % LOOP_BARS.len()];
+    }
+}
+
+pub fn update_looper(
+    transport: Res<Transport>,
+    mut presses: EventReader<PressNote>,
+    mut looper: ResMut<Looper>,
+    mut label: Query<&mut Text, With<LooperLabel>>,
+) {
+    let l = &mut *looper;
+
+    if !transport.playing() {
+        presses.clear();
+        l.recording = None;
+        l.silence();
+        // the loop starts again from its beginning with the transport
+        l.origin = 0.;
+        l.played = None;
+    }
+    else {
+        let position = transport.position() - l.origin;
+
+        if let Some(r) = &mut l.recording {
+            if transport.position() >= r.start {
+                for press in presses.read() {
+                    r.events.push((position.rem_euclid(l.length), press.position, press.pressed));
+                    if press.pressed {
+                        r.held.insert(press.position);
+                    }
+                    else {
+                        r.held.remove(&press.position);
+                    }
+                }
+            }
+            if transport.position() >= r.start + l.length {
+                l.finish_recording();
+            }
+        }
+        presses.clear();
+
+        // the loop goes on while the first layer is recorded
+        if l.length > 0. && position >= 0. {
+            l.play(position);
+        }
+    }
+
+    let value = l.label(&transport);
+    for mut text in &mut label {
+        if text.sections[0].value != value {
+            text.sections[0].value = value.clone();
+        }
+    }
+}
@@ -1,29 +1,103 @@
 use bevy::prelude::*;
 use bevy::audio::AudioPlugin;
 use bevy::audio::AddAudioSource;
+use bevy::utils::{HashSet, HashMap};
 
 #[derive(Component)]
 struct Playing(bool);
 
+// how many inputs (keys, fingers, ...) are holding the note
+#[derive(Component)]
+struct Held(usize);
+
 #[derive(Resource)]
 struct ChordJustChanged(bool);
 
 #[derive(Resource)]
 struct BaseNote(usize);
 
+// what is shown next to the circle
+#[derive(States, Debug, Clone, Copy, Default, Eq, PartialEq, Hash)]
+enum View {
+    #[default]
+    String,
+    Harp,
+    Membrane,
+    Scope,
+    Tonnetz,
+    PianoRoll,
+    Staff,
+}
+
+static VIEWS: [View; 7] = [
+    View::String, View::Harp, View::Membrane, View::Scope, View::Tonnetz, View::PianoRoll, View::Staff,
+];
+
+impl View {
+    fn next(self) -> Self {
+        let i = VIEWS.iter().position(|&v| v == self).unwrap();
+        VIEWS[(i + 1) % VIEWS.len()]
+    }
+
+    fn previous(self) -> Self {
+        let i = VIEWS.iter().position(|&v| v == self).unwrap();
+        VIEWS[(i + VIEWS.len() - 1) % VIEWS.len()]
+    }
+}
+
 use std::f32::consts::PI;
 
 const BASE_FREQUENCY: f32 = 55.0;
+// midi number of A1, the note at `BASE_FREQUENCY`, which is the note 0 of `BaseNote`
+const BASE_MIDI: i32 = 33;
 
 const N_OCTAVES : usize = 2;
 
 const STRING_LENGTH: f32 = 500.;
 
 mod sound;
-use sound::{Synth, create_samples};
+use sound::{Synth, create_samples, sync_new_sinks};
+
+mod chords;
 
 mod keyboard;
-use keyboard::keyboard_input_system;
+use keyboard::{KeyboardMode, keyboard_input_system, view_input_system, create_keyboard_mode_label};
+
+mod transport;
+use transport::{Transport, create_transport_label, transport_input_system, update_transport};
+
+mod looper;
+use looper::{Looper, create_looper_label, looper_input_system, update_looper};
+
+mod arpeggiator;
+use arpeggiator::{Arpeggiator, create_arpeggiator_label, arpeggiator_input_system, update_arpeggiator};
+
+mod text_input;
+use text_input::{TextEntry, TextSubmitted, create_text_entry_label, text_entry_system};
+
+mod chord_input;
+use chord_input::{chord_input_system, chord_from_args};
+
+mod sequencer;
+use sequencer::{Sequencer, create_sequencer_label, sequencer_system};
+
+mod midi;
+
+mod abc;
+
+mod playback;
+use playback::{Playback, create_playback_label, open_song_from_args, playback_system};
+
+mod musicxml;
+
+mod session;
+use session::{Session, create_session_label, record_session};
+
+mod piano;
+use piano::{create_piano, draw_piano};
+
+mod pointer;
+use pointer::{CircleMode, pointer_input_system, circle_mode_system, create_circle_mode_label, draw_hover};
 
 mod circle;
 use circle::{create_circle, draw_notes, create_note_names};
@@ -31,6 +105,41 @@ use circle::{create_circle, draw_notes, create_note_names};
 mod string;
 use string::{StringState, StringParams};
 
+mod harp;
+use harp::{change_harp, update_harp, draw_harp, clear_harp};
+
+mod membrane;
+use membrane::{create_membrane, update_membrane, clear_membrane};
+
+mod spectrum;
+
+mod diagnostics;
+use diagnostics::{StringProbe, create_diagnostics, toggle_diagnostics, hide_diagnostics, record_probe, draw_diagnostics};
+
+mod scope;
+use scope::{create_scope, draw_scope, clear_scope};
+
+mod tonnetz;
+use tonnetz::{TonnetzHistory, create_tonnetz, update_tonnetz, draw_tonnetz, clear_tonnetz};
+
+mod piano_roll;
+use piano_roll::{PianoRoll, record_piano_roll, draw_piano_roll};
+
+mod staff;
+use staff::{StaffHistory, create_staff, record_staff_history, draw_staff, clear_staff};
+
+mod interval;
+use interval::{create_interval_label, draw_interval};
+
+mod challenge;
+use challenge::{Challenge, create_challenge_label, challenge_system, draw_challenge};
+
+mod quiz;
+use quiz::{Quiz, create_quiz_label, quiz_from_args, quiz_system};
+
+mod panel;
+use panel::{create_panel, slider_system, preset_system, update_sliders, toggle_panel};
+
 
 
 #[derive(Component, Debug, Clone)]
@@ -44,6 +153,106 @@ struct Angle(f32);
 #[derive(Event)]
 struct UpdateNoteMapping;
 
+// sent by the inputs when they start or stop holding a note
+#[derive(Event, Clone, Copy)]
+struct PressNote {
+    position: usize,
+    pressed: bool,
+}
+
+// the notes switched on until they are switched off again
+#[derive(Default)]
+struct LatchSet(HashSet<usize>);
+
+impl LatchSet {
+    fn toggle(&mut self, position: usize) -> PressNote {
+        let pressed = self.0.insert(position);
+        if !pressed {
+            self.0.remove(&position);
+        }
+        PressNote {position, pressed}
+    }
+
+    // switches the note on, if it is not already
+    fn latch(&mut self, position: usize) -> Option<PressNote> {
+        self.0.insert(position).then_some(PressNote {position, pressed: true})
+    }
+
+    fn clear(&mut self) -> Vec<PressNote> {
+        self.0.drain().map(|position| PressNote {position, pressed: false}).collect()
+    }
+}
+
+// The notes latched by each input, apart, so that an input leaving
+// its latching mode releases only its own notes
+#[derive(Resource, Default)]
+struct Latched {
+    // by the latch mode of the keyboard
+    keys: LatchSet,
+    // by the toggle mode of the circle
+    circle: LatchSet,
+    // by the chord typed in the text box or given with `--chord`
+    chord: LatchSet,
+}
+
+impl Latched {
+    fn clear(&mut self) -> Vec<PressNote> {
+        let mut presses = self.keys.clear();
+        presses.extend(self.circle.clear());
+        presses.extend(self.chord.clear());
+        presses
+    }
+}
+
+// the playing notes are not shown, so that they can be recognised by ear
+#[derive(Resource, Default)]
+struct HideNotes(bool);
+
+// The command line: the options like `--chord Cmaj7` (or `--chord=Cmaj7`),
+// and the files to open
+#[derive(Resource, Default)]
+struct CommandLine {
+    options: HashMap<String, String>,
+    files: Vec<String>,
+}
+
+impl CommandLine {
+    fn from_env() -> Self {
+        let mut command_line = CommandLine::default();
+        let mut args = std::env::args().skip(1);
+
+        while let Some(arg) = args.next() {
+            match arg.strip_prefix("--") {
+                Some(option) => {
+                    let (name, value) = match option.split_once('=') {
+                        Some((name, value)) => (name.to_string(), value.to_string()),
+                        None => (option.to_string(), args.next().unwrap_or_default()),
+                    };
+                    command_line.options.insert(name, value);
+                }
+                None => command_line.files.push(arg),
+            }
+        }
+
+        command_line
+    }
+
+    fn option(&self, name: &str) -> Option<&str> {
+        self.options.get(name).map(|v| v.as_str())
+    }
+}
+
+// the systems sending `PressNote`
+#[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
+struct NoteInput;
+
+// sent each time a note starts or stops being played
+#[derive(Event, Clone)]
+struct NoteEvent {
+    position: NotePosition,
+    on: bool,
+}
+
 impl NotePosition {
     fn new(number: usize, octave: usize) -> Self {
         NotePosition(octave * 12 + number)
@@ -82,6 +291,19 @@ impl Note {
         2.0f32.powf(self.0) * BASE_FREQUENCY
     }
 
+    fn from_freq(f: f32) -> Self {
+        Note((f / BASE_FREQUENCY).log2())
+    }
+
+    // number of half tones from the base frequency, rounded to the nearest one
+    fn half_tones(self) -> i32 {
+        (self.0 * 12.).round() as i32
+    }
+
+    fn name(self) -> &'static str {
+        NOTE_NAMES[self.half_tones().rem_euclid(12) as usize]
+    }
+
     fn color(self) -> Color {
         Color::rgb(
             0.9,
@@ -91,7 +313,7 @@ impl Note {
     }
 
     fn relative_length(self) -> f32 {
-        2.0f32.powf(-self.0 as f32)
+        2.0f32.powf(-self.0)
     }
 }
 
@@ -104,19 +326,92 @@ fn main() {
         .add_plugins(bevy_framepace::FramepacePlugin)
         .add_audio_source::<Synth>()
         .add_event::<UpdateNoteMapping>()
+        .add_event::<NoteEvent>()
+        .add_event::<PressNote>()
+        .add_state::<View>()
+        .insert_resource(CommandLine::from_env())
         .add_systems(Startup, setup)
         .add_systems(Startup, init_string)
         .add_systems(PostStartup, create_circle)
+        .add_systems(Startup, create_panel)
+        .add_systems(Startup, create_diagnostics)
+        .add_systems(Startup, create_interval_label)
 
         .add_systems(Update, create_note_names.run_if(on_event::<UpdateNoteMapping>()))
         .add_systems(Update, create_samples.run_if(on_event::<UpdateNoteMapping>()))
+        .add_systems(Update, create_piano.run_if(on_event::<UpdateNoteMapping>()))
         .add_systems(Update, change_string)
 
 
-        .add_systems(Update, keyboard_input_system)
+        .init_resource::<CircleMode>()
+        .init_resource::<KeyboardMode>()
+        .init_resource::<Latched>()
+        .add_systems(Startup, (create_circle_mode_label, create_keyboard_mode_label))
+        .add_systems(Update, (keyboard_input_system, pointer_input_system, circle_mode_system).in_set(NoteInput))
+        .add_systems(Update, (hold_notes, update_arpeggiator, update_looper, play_notes).chain().after(NoteInput))
+        .init_resource::<Arpeggiator>()
+        .add_systems(Startup, create_arpeggiator_label)
+        .add_systems(Update, arpeggiator_input_system)
+        .init_resource::<Transport>()
+        .add_systems(Startup, create_transport_label)
+        .init_resource::<Looper>()
+        .add_systems(Startup, create_looper_label)
+        .add_systems(Update, looper_input_system.after(transport_input_system).before(NoteInput))
+        .init_resource::<Playback>()
+        .add_systems(Startup, (open_song_from_args, create_playback_label).chain())
+        .add_systems(Update, playback_system.in_set(NoteInput))
+        .add_systems(Update, sync_new_sinks)
+        .init_resource::<Session>()
+        .add_systems(Startup, create_session_label)
+        .add_systems(Update, record_session.after(play_notes))
+        .add_systems(Update, (transport_input_system, update_transport).chain().after(text_entry_system).before(NoteInput))
+        .init_resource::<TextEntry>()
+        .add_event::<TextSubmitted>()
+        .add_systems(Startup, create_text_entry_label)
+        .add_systems(Update, text_entry_system.before(NoteInput))
+        .init_resource::<Sequencer>()
+        .add_systems(Startup, create_sequencer_label)
+        .add_systems(Update, sequencer_system.in_set(NoteInput))
+        .add_systems(PostStartup, chord_from_args)
+        .add_systems(Update, chord_input_system.in_set(NoteInput))
+        .init_resource::<Quiz>()
+        .init_resource::<HideNotes>()
+        .add_systems(Startup, create_quiz_label)
+        .add_systems(PostStartup, quiz_from_args)
+        .init_resource::<Challenge>()
+        .add_systems(Startup, create_challenge_label)
+        .add_systems(Update, (challenge_system.after(play_notes), draw_challenge))
+        .add_systems(Update, quiz_system.in_set(NoteInput))
+        .add_systems(Update, view_input_system)
         .add_systems(Update, draw_notes)
-        .add_systems(Update, draw_string)
-        .add_systems(Update, update_string)
+        .add_systems(Update, draw_piano)
+        .add_systems(Update, draw_hover)
+        .add_systems(Update, draw_interval)
+        .add_systems(Update, (draw_string, update_string).run_if(in_state(View::String)))
+        .add_systems(Update, (toggle_diagnostics, record_probe.after(update_string), draw_diagnostics)
+                     .run_if(in_state(View::String)))
+        .add_systems(OnExit(View::String), hide_diagnostics)
+        .add_systems(Update, (change_harp, update_harp, draw_harp).chain().run_if(in_state(View::Harp)))
+        .add_systems(OnExit(View::Harp), clear_harp)
+        .add_systems(OnEnter(View::Membrane), create_membrane)
+        .add_systems(Update, update_membrane.run_if(in_state(View::Membrane)))
+        .add_systems(OnExit(View::Membrane), clear_membrane)
+        .add_systems(OnEnter(View::Scope), create_scope)
+        .add_systems(Update, draw_scope.run_if(in_state(View::Scope)))
+        .add_systems(OnExit(View::Scope), clear_scope)
+        .init_resource::<TonnetzHistory>()
+        .add_systems(OnEnter(View::Tonnetz), create_tonnetz)
+        .add_systems(Update, (update_tonnetz, draw_tonnetz).chain().run_if(in_state(View::Tonnetz)))
+        .add_systems(OnExit(View::Tonnetz), clear_tonnetz)
+        .init_resource::<PianoRoll>()
+        .add_systems(Update, record_piano_roll)
+        .add_systems(Update, draw_piano_roll.run_if(in_state(View::PianoRoll)))
+        .init_resource::<StaffHistory>()
+        .add_systems(OnEnter(View::Staff), create_staff)
+        .add_systems(Update, record_staff_history)
+        .add_systems(Update, draw_staff.run_if(in_state(View::Staff)))
+        .add_systems(OnExit(View::Staff), clear_staff)
+        .add_systems(Update, (slider_system, preset_system, update_sliders, toggle_panel))
         .run();
 }
 
@@ -126,7 +421,7 @@ fn setup(
     ) {
     commands.spawn(Camera2dBundle::default());
 
-    // number of half tones from A0
+    // number of half tones from A1
     commands.insert_resource(BaseNote(27));
     commands.insert_resource(ChordJustChanged(false));
 
@@ -135,7 +430,7 @@ fn setup(
             let note_pos = NotePosition::new(i, height);
 
             let angle = note_pos.angle();
-            commands.spawn((note_pos, angle, Playing(false)));
+            commands.spawn((note_pos, angle, Playing(false), Held(0)));
         }
     }
 
@@ -152,7 +447,7 @@ fn setup(
         text: Text::from_section("Be sure to be on qwerty, and start typing on keys ...", 
                                  text_style.clone()),
         transform: Transform::from_translation(
-            Vec3::new(0., -300., -1.)
+            Vec3::new(0., 330., -1.)
         ),
         ..default()
     };
@@ -166,37 +461,77 @@ const N: usize = 150;
 fn init_string(
     mut commands: Commands
     ){
-    commands.spawn(
+    commands.spawn((
         VibratingString {
-            params: StringParams {
-                length: STRING_LENGTH,
-                n_samples: N,
-                dt: 0.02,
-                c: 100.,
-                chord: vec![],
-                spring_coeff: 10.00,
-                solid_friction_coeff: 50.,
-                liquid_friction_coeff: 0.015,
-                steps_per_render: 10,
-                excitation_coeff: 0.05,
-            },
+            params: StringParams::default(),
             state: StringState::new_flat(N)
-        }
-    );
+        },
+        MainString,
+        StringProbe::default(),
+    ));
 }
 
-pub static NOTE_NAMES: [&'static str; 12] = 
+pub static NOTE_NAMES: [&str; 12] = 
     ["la", "la#", "si", "do", "do#", "re", "re#", "mi", "fa", "fa#", "sol", "sol#"];
 
 
+#[derive(Component)]
+struct MainString;
+
 #[derive(Bundle, Debug, Clone)]
 struct VibratingString {
     params: StringParams,
     state: StringState,
 }
 
+fn hold_notes(
+    mut presses: EventReader<PressNote>,
+    mut notes: Query<(&NotePosition, &mut Held)>,
+) {
+    for press in presses.read() {
+        for (position, mut held) in &mut notes {
+            if position.0 != press.position {
+                continue
+            }
+            if press.pressed {
+                held.0 += 1;
+            }
+            else {
+                held.0 = held.0.saturating_sub(1);
+            }
+        }
+    }
+}
+
+fn play_notes(
+    mut notes: Query<(&NotePosition, &Held, &mut Playing, Option<&AudioSink>)>,
+    arpeggiator: Res<Arpeggiator>,
+    looper: Res<Looper>,
+    mut chord_changed: ResMut<ChordJustChanged>,
+    mut note_events: EventWriter<NoteEvent>,
+) {
+    for (position, held, mut playing, sink) in &mut notes {
+        let on = arpeggiator.sounding(position.0, held.0 > 0) || looper.sounding(position.0);
+        if on == playing.0 {
+            continue
+        }
+
+        playing.0 = on;
+        chord_changed.0 = true;
+        note_events.send(NoteEvent {position: position.clone(), on});
+
+        if let Some(sink) = sink {
+            if on {sink.play()} else {sink.pause()}
+        }
+    }
+}
+
+// the steps taken to settle the string on a new chord, as many as with the default dt,
+// so that a small dt set on the panel does not freeze the frame
+const MAX_SETTLE_STEPS: usize = 25_000;
+
 fn change_string(
-    mut string: Query<(&mut StringState, &mut StringParams)>,
+    mut string: Query<(&mut StringState, &mut StringParams), With<MainString>>,
     notes: Query<(&NotePosition, &Playing)>,
     mut chord_changed: ResMut<ChordJustChanged>,
 ) {
@@ -227,13 +562,13 @@ fn change_string(
         p.chord.push(note);
     }
 
-    for _ in 0..(p.length/p.dt) as usize{
+    for _ in 0..((p.length/p.dt) as usize).min(MAX_SETTLE_STEPS) {
         s.step(&p);
     }
 }
 
 fn update_string(
-    mut string: Query<(&mut StringState, &mut StringParams)>,
+    mut string: Query<(&mut StringState, &mut StringParams), With<MainString>>,
 ) {
     if let Ok((mut s, params)) = string.get_single_mut() {
         for _ in 0..params.steps_per_render {
@@ -244,8 +579,14 @@ fn update_string(
 
 fn draw_string(
     gizmos: Gizmos,
-    string: Query<(&StringState, &StringParams)>,
+    hide: Res<HideNotes>,
+    string: Query<(&StringState, &StringParams), With<MainString>>,
 ) {
+    // the shape of the string and its junctions give the notes away
+    if hide.0 {
+        return
+    }
+
     if let Ok((s, p)) = string.get_single() {
         s.draw(p, gizmos)
     }
@@ -0,0 +1,174 @@
+use bevy::prelude::*;
+
+use bevy::sprite::MaterialMesh2dBundle;
+use bevy::render::render_resource::PrimitiveTopology;
+use bevy::render::mesh::Indices;
+
+use super::{Note, NotePosition, Playing, HideNotes};
+
+use std::f32::consts::PI;
+
+// number of samples along each side of the membrane
+const SIDE: usize = 60;
+const SIZE: f32 = 400.;
+const OFFSET: Vec3 = Vec3::new(300., 0., -1.);
+
+// the simulation works in units where the distance between two samples is 1
+const C: f32 = 1.;
+const DT: f32 = 0.5;
+const STEPS_PER_RENDER: usize = 20;
+const FRICTION_COEFF: f32 = 0.01;
+const EXCITATION_COEFF: f32 = 0.05;
+// frequency of the excitation for a note of relative length 1
+const BASE_FREQUENCY: f32 = 0.02;
+// where the membrane is excited.
+// Off-center, so that the asymmetric modes are excited too
+const EXCITATION_POINT: (usize, usize) = (SIDE * 3 / 10, SIDE * 4 / 10);
+// how fast the heat-map forgets the past amplitudes, between 0 and 1
+const MEMORY: f32 = 0.98;
+
+// A square membrane fixed on its borders.
+// The heat-map shows the average squared displacement of each point,
+// so the nodal lines are the dark ones.
+#[derive(Component)]
+pub struct Membrane {
+    time: f32,
+    last: Vec<f32>,
+    current: Vec<f32>,
+    energy: Vec<f32>,
+}
+
+fn index(i: usize, j: usize) -> usize {
+    i * SIDE + j
+}
+
+impl Membrane {
+    pub fn new_flat() -> Self {
+        Self {
+            time: 0.,
+            last: vec![0.; SIDE * SIDE],
+            current: vec![0.; SIDE * SIDE],
+            energy: vec![0.; SIDE * SIDE],
+        }
+    }
+
+    fn step(&mut self, chord: &[Note]) {
+        self.time += DT;
+
+        let excitation: f32 = chord.iter()
+            .map(|note| EXCITATION_COEFF * f32::sin(2. * PI * BASE_FREQUENCY * self.time / note.relative_length()))
+            .sum();
+
+        // the border stays at 0
+        for i in 1..SIDE-1 {
+            for j in 1..SIDE-1 {
+                let u = |i, j| self.current[index(i, j)];
+                let laplacian = u(i+1, j) + u(i-1, j) + u(i, j+1) + u(i, j-1) - 4. * u(i, j);
+                let velocity = (u(i, j) - self.last[index(i, j)]) / DT;
+
+                let mut acc = C * C * laplacian - FRICTION_COEFF * velocity;
+                if (i, j) == EXCITATION_POINT {
+                    acc += excitation;
+                }
+
+                // attention: on inverse last et current
+                self.last[index(i, j)] = 2. * u(i, j) - self.last[index(i, j)] + DT * DT * acc;
+            }
+        }
+
+        std::mem::swap(&mut self.last, &mut self.current);
+    }
+
+    fn accumulate(&mut self) {
+        for (e, u) in self.energy.iter_mut().zip(&self.current) {
+            *e = MEMORY * *e + (1. - MEMORY) * u * u;
+        }
+    }
+
+    fn colors(&self) -> Vec<[f32; 4]> {
+        let max = self.energy.iter().fold(f32::EPSILON, |m, &e| m.max(e));
+        self.energy.iter()
+            .map(|&e| {
+                let x = (e / max).sqrt();
+                [x, x * x, 0.3 * (1. - x), 1.]
+            })
+            .collect()
+    }
+}
+
+fn grid_mesh() -> Mesh {
+    let mut positions = Vec::new();
+    for i in 0..SIDE {
+        for j in 0..SIDE {
+            let x = (j as f32 / (SIDE - 1) as f32 - 0.5) * SIZE;
+            let y = (i as f32 / (SIDE - 1) as f32 - 0.5) * SIZE;
+            positions.push([x, y, 0.]);
+        }
+    }
+
+    let mut indices = Vec::new();
+    for i in 0..SIDE-1 {
+        for j in 0..SIDE-1 {
+            let a = index(i, j) as u32;
+            let b = index(i, j+1) as u32;
+            let c = index(i+1, j) as u32;
+            let d = index(i+1, j+1) as u32;
+            indices.extend([a, b, d, a, d, c]);
+        }
+    }
+
+    Mesh::new(PrimitiveTopology::TriangleList)
+        .with_inserted_attribute(Mesh::ATTRIBUTE_POSITION, positions)
+        .with_inserted_attribute(Mesh::ATTRIBUTE_COLOR, Membrane::new_flat().colors())
+        .with_indices(Some(Indices::U32(indices)))
+}
+
+pub fn create_membrane(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+) {
+    let mesh = MaterialMesh2dBundle {
+        mesh: meshes.add(grid_mesh()).into(),
+        material: materials.add(ColorMaterial::from(Color::WHITE)),
+        transform: Transform::from_translation(OFFSET),
+        ..default()
+    };
+
+    commands.spawn((mesh, Membrane::new_flat()));
+}
+
+pub fn update_membrane(
+    mut meshes: ResMut<Assets<Mesh>>,
+    hide: Res<HideNotes>,
+    mut membrane: Query<(&mut Membrane, &Handle<Mesh>, &mut Visibility)>,
+    notes: Query<(&NotePosition, &Playing)>,
+) {
+    let chord: Vec<Note> = notes.iter()
+        .filter(|(_, p)| p.0)
+        .map(|(x, _)| x.note(0))
+        .collect();
+
+    for (mut m, handle, mut visibility) in &mut membrane {
+        // the membrane keeps vibrating, but its pattern gives the notes away
+        *visibility = if hide.0 {Visibility::Hidden} else {Visibility::Inherited};
+
+        for _ in 0..STEPS_PER_RENDER {
+            m.step(&chord);
+        }
+        m.accumulate();
+
+        if let Some(mesh) = meshes.get_mut(handle) {
+            mesh.insert_attribute(Mesh::ATTRIBUTE_COLOR, m.colors());
+        }
+    }
+}
+
+pub fn clear_membrane(
+    mut commands: Commands,
+    membrane: Query<Entity, With<Membrane>>,
+) {
+    for e in &membrane {
+        commands.entity(e).despawn();
+    }
+}
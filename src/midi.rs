@@ -0,0 +1,384 @@
+use bevy::utils::HashMap;
+
+use super::playback::{Song, SongNote, Track};
+
+// the channel of the drums in general midi
+const DRUM_CHANNEL: u8 = 9;
+// 120 bpm, until the file says otherwise
+const DEFAULT_TEMPO: u32 = 500_000;
+
+// what matters to us in a midi track
+enum Event {
+    NoteOn {channel: u8, pitch: u8},
+    NoteOff {channel: u8, pitch: u8},
+    // microseconds per quarter note
+    Tempo(u32),
+    Name(String),
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    index: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn done(&self) -> bool {
+        self.index >= self.bytes.len()
+    }
+
+    fn take(&mut self, n: usize) -> Result<&'a [u8], String> {
+        let end = self.index + n;
+        let taken = self.bytes.get(self.index..end).ok_or("unexpected end of file")?;
+        self.index = end;
+        Ok(taken)
+    }
+
+    fn byte(&mut self) -> Result<u8, String> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn peek(&self) -> Result<u8, String> {
+        self.bytes.get(self.index).copied().ok_or("unexpected end of file".to_string())
+    }
+
+    fn u16(&mut self) -> Result<u16, String> {
+        let b = self.take(2)?;
+        Ok(u16::from_be_bytes([b[0], b[1]]))
+    }
+
+    fn u32(&mut self) -> Result<u32, String> {
+        let b = self.take(4)?;
+        Ok(u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+    }
+
+    // a variable length quantity, 7 bits per byte
+    fn varlen(&mut self) -> Result<u32, String> {
+        let mut value = 0;
+        for _ in 0..4 {
+            let b = self.byte()?;
+            value = (value << 7) | (b & 0x7f) as u32;
+            if b & 0x80 == 0 {
+                return Ok(value)
+            }
+        }
+        Err("variable length quantity too long".to_string())
+    }
+
+    fn chunk(&mut self) -> Result<(&'a [u8], &'a [u8]), String> {
+        let kind = self.take(4)?;
+        let length = self.u32()? as usize;
+        Ok((kind, self.take(length)?))
+    }
+}
+
+// the events of a track chunk, with their time in ticks
+fn parse_track(bytes: &[u8]) -> Result<Vec<(u64, Event)>, String> {
+    let mut reader = Reader {bytes, index: 0};
+    let mut events = Vec::new();
+    let mut tick = 0;
+    let mut running_status = None;
+
+    while !reader.done() {
+        tick += reader.varlen()? as u64;
+
+        let status = if reader.peek()? & 0x80 != 0 {reader.byte()?} else {
+            running_status.ok_or("data byte without a status")?
+        };
+
+        match status {
+            0xff => {
+                let kind = reader.byte()?;
+                let length = reader.varlen()? as usize;
+                let data = reader.take(length)?;
+                match kind {
+                    0x51 if length == 3 => {
+                        events.push((tick, Event::Tempo(u32::from_be_bytes([0, data[0], data[1], data[2]]))));
+                    }
+                    0x03 => events.push((tick, Event::Name(String::from_utf8_lossy(data).trim().to_string()))),
+                    0x2f => break,
+                    _ => {}
+                }
+            }
+            0xf0 | 0xf7 => {
+                let length = reader.varlen()? as usize;
+                reader.take(length)?;
+            }
+            _ => {
+                running_status = Some(status);
+                let channel = status & 0x0f;
+                match status & 0xf0 {
+                    0x80 => {
+                        let pitch = reader.byte()?;
+                        reader.byte()?;
+                        events.push((tick, Event::NoteOff {channel, pitch}));
+                    }
+                    0x90 => {
+                        let pitch = reader.byte()?;
+                        let velocity = reader.byte()?;
+                        if velocity == 0 {
+                            events.push((tick, Event::NoteOff {channel, pitch}));
+                        }
+                        else {
+                            events.push((tick, Event::NoteOn {channel, pitch}));
+                        }
+                    }
+                    0xa0 | 0xb0 | 0xe0 => {reader.take(2)?;}
+                    0xc0 | 0xd0 => {reader.take(1)?;}
+                    _ => return Err(format!("unknown status byte {:#x}", status)),
+                }
+            }
+        }
+    }
+
+    Ok(events)
+}
+
+// converts ticks to seconds, following the changes of tempo
+struct TempoMap {
+    // the tick, the time in seconds and the tempo from there on
+    changes: Vec<(u64, f64, u32)>,
+    ticks_per_quarter: f64,
+}
+
+impl TempoMap {
+    fn new(mut tempos: Vec<(u64, u32)>, ticks_per_quarter: f64) -> Self {
+        tempos.sort_by_key(|(tick, _)| *tick);
+
+        let mut changes = vec![(0, 0., DEFAULT_TEMPO)];
+        for (tick, tempo) in tempos {
+            let seconds = Self::seconds_from(changes.last().unwrap(), tick, ticks_per_quarter);
+            changes.push((tick, seconds, tempo));
+        }
+
+        TempoMap {changes, ticks_per_quarter}
+    }
+
+    fn seconds_from(&(start, seconds, tempo): &(u64, f64, u32), tick: u64, ticks_per_quarter: f64) -> f64 {
+        seconds + (tick - start) as f64 * tempo as f64 / 1e6 / ticks_per_quarter
+    }
+
+    fn seconds(&self, tick: u64) -> f32 {
+        let change = self.changes.iter().rev().find(|(t, _, _)| *t <= tick).unwrap();
+        Self::seconds_from(change, tick, self.ticks_per_quarter) as f32
+    }
+}
+
+// Reads a standard midi file.
+// The tracks of the song are the tracks of the file, or its channels if it has only one track.
+pub fn parse_midi(bytes: &[u8], name: &str) -> Result<Song, String> {
+    let mut reader = Reader {bytes, index: 0};
+
+    let (kind, header) = reader.chunk()?;
+    if kind != b"MThd" || header.len() < 6 {
+        return Err("not a midi file".to_string())
+    }
+    let mut header = Reader {bytes: header, index: 0};
+    let format = header.u16()?;
+    let n_tracks = header.u16()?;
+    let division = header.u16()?;
+
+    let mut tracks = Vec::new();
+    while !reader.done() && tracks.len() < n_tracks as usize {
+        let (kind, data) = reader.chunk()?;
+        // unknown chunks are skipped
+        if kind == b"MTrk" {
+            tracks.push(parse_track(data)?);
+        }
+    }
+
+    let tempos = tracks.iter().flatten()
+        .filter_map(|(tick, e)| match e {Event::Tempo(t) => Some((*tick, *t)), _ => None})
+        .collect();
+
+    let tempo_map = if division & 0x8000 == 0 {
+        TempoMap::new(tempos, division as f64)
+    }
+    else {
+        // in frames per second and ticks per frame, so the tempo does not matter
+        let fps = -((division >> 8) as i8) as f64;
+        let ticks_per_frame = (division & 0xff) as f64;
+        TempoMap {changes: vec![(0, 0., 1_000_000)], ticks_per_quarter: fps * ticks_per_frame}
+    };
+
+    let mut song = Song {name: name.to_string(), ..Default::default()};
+    // the index in the song of each track of the file, or of each channel
+    let mut song_tracks: HashMap<(usize, u8), usize> = HashMap::new();
+    // the notes being played, with their start time
+    let mut started: HashMap<(usize, u8, u8), Vec<f32>> = HashMap::new();
+
+    for (i, track) in tracks.iter().enumerate() {
+        let mut track_name = None;
+
+        for (tick, event) in track {
+            let time = tempo_map.seconds(*tick);
+            match *event {
+                Event::Name(ref n) if !n.is_empty() => track_name = Some(n.clone()),
+                Event::NoteOn {channel, pitch} => {
+                    started.entry((i, channel, pitch)).or_default().push(time);
+                }
+                Event::NoteOff {channel, pitch} => {
+                    let start = match started.get_mut(&(i, channel, pitch)) {
+                        Some(s) if !s.is_empty() => s.remove(0),
+                        _ => continue
+                    };
+
+                    let key = if format == 0 {(0, channel)} else {(i, 0)};
+                    let track = *song_tracks.entry(key).or_insert_with(|| {
+                        let name = if channel == DRUM_CHANNEL {"drums".to_string()}
+                            else if format == 0 {format!("channel {}", channel + 1)}
+                            else {track_name.clone().unwrap_or(format!("track {}", i + 1))};
+                        // the drums would only play random notes
+                        song.tracks.push(Track {name, muted: channel == DRUM_CHANNEL});
+                        song.tracks.len() - 1
+                    });
+
+                    song.notes.push(SongNote {start, duration: time - start, pitch, track});
+                }
+                _ => {}
+            }
+        }
+    }
+
+    if song.notes.is_empty() {
+        return Err("no notes in the file".to_string())
+    }
+
+    song.notes.sort_by(|a, b| a.start.total_cmp(&b.start));
+    Ok(song)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const END_OF_TRACK: [u8; 4] = [0x00, 0xff, 0x2f, 0x00];
+
+    fn file(format: u16, division: u16, tracks: &[&[u8]]) -> Vec<u8> {
+        let mut bytes = b"MThd".to_vec();
+        bytes.extend(6u32.to_be_bytes());
+        bytes.extend(format.to_be_bytes());
+        bytes.extend((tracks.len() as u16).to_be_bytes());
+        bytes.extend(division.to_be_bytes());
+        for track in tracks {
+            bytes.extend(b"MTrk");
+            bytes.extend((track.len() as u32 + 4).to_be_bytes());
+            bytes.extend(*track);
+            bytes.extend(END_OF_TRACK);
+        }
+        bytes
+    }
+
+    // the start, duration, pitch and track of each note
+    fn notes(song: &Song) -> Vec<(f32, f32, u8, usize)> {
+        song.notes.iter().map(|n| (n.start, n.duration, n.pitch, n.track)).collect()
+    }
+
+    #[test]
+    fn running_status() {
+        // the second and following events of the channel leave out their status,
+        // a note on with no velocity being a note off
+        let track = [
+            0x00, 0x90, 60, 100,
+            0x60, 60, 0,
+            0x00, 64, 100,
+            0x60, 0x80, 64, 0,
+        ];
+        let song = parse_midi(&file(0, 96, &[&track]), "test").unwrap();
+        // 96 ticks per quarter note at 120 bpm
+        assert_eq!(notes(&song), vec![(0., 0.5, 60, 0), (0.5, 0.5, 64, 0)]);
+    }
+
+    #[test]
+    fn tempo_changes() {
+        // the tempo is in the first track, and applies to the notes of the others
+        let tempo = [
+            0x00, 0xff, 0x51, 0x03, 0x0f, 0x42, 0x40,
+            0x81, 0x40, 0xff, 0x51, 0x03, 0x03, 0xd0, 0x90,
+        ];
+        let melody = [
+            0x00, 0x90, 60, 100,
+            0x81, 0x40, 0x80, 60, 0,
+            0x00, 0x90, 62, 100,
+            0x60, 0x80, 62, 0,
+        ];
+        let song = parse_midi(&file(1, 96, &[&tempo, &melody]), "test").unwrap();
+        // 60 bpm for two beats, then 240 bpm
+        assert_eq!(notes(&song), vec![(0., 2., 60, 0), (2., 0.25, 62, 0)]);
+    }
+
+    #[test]
+    fn smpte_division() {
+        // 25 frames per second of 40 ticks, the tempo being ignored
+        let track = [
+            0x00, 0xff, 0x51, 0x03, 0x0f, 0x42, 0x40,
+            0x00, 0x90, 60, 100,
+            0x83, 0x74, 0x80, 60, 0,
+        ];
+        let song = parse_midi(&file(0, 0xe728, &[&track]), "test").unwrap();
+        assert_eq!(notes(&song), vec![(0., 0.5, 60, 0)]);
+    }
+
+    #[test]
+    fn format_0_is_split_by_channel() {
+        let track = [
+            0x00, 0x90, 60, 100,
+            0x00, 0x91, 48, 100,
+            0x00, 0x99, 36, 100,
+            0x60, 0x80, 60, 0,
+            0x00, 0x81, 48, 0,
+            0x00, 0x89, 36, 0,
+        ];
+        let song = parse_midi(&file(0, 96, &[&track]), "test").unwrap();
+
+        let tracks: Vec<(&str, bool)> = song.tracks.iter().map(|t| (t.name.as_str(), t.muted)).collect();
+        assert_eq!(tracks, vec![("channel 1", false), ("channel 2", false), ("drums", true)]);
+        let pitches: Vec<(u8, usize)> = song.notes.iter().map(|n| (n.pitch, n.track)).collect();
+        assert_eq!(pitches, vec![(60, 0), (48, 1), (36, 2)]);
+    }
+
+    #[test]
+    fn tracks_of_format_1_keep_their_names() {
+        let piano = [
+            0x00, 0xff, 0x03, 0x05, b'p', b'i', b'a', b'n', b'o',
+            0x00, 0x90, 60, 100,
+            0x60, 0x80, 60, 0,
+        ];
+        let drums = [
+            0x00, 0x99, 36, 100,
+            0x60, 0x89, 36, 0,
+        ];
+        let song = parse_midi(&file(1, 96, &[&piano, &drums]), "test").unwrap();
+
+        let tracks: Vec<(&str, bool)> = song.tracks.iter().map(|t| (t.name.as_str(), t.muted)).collect();
+        assert_eq!(tracks, vec![("piano", false), ("drums", true)]);
+    }
+
+    #[test]
+    fn truncated_files_are_errors() {
+        let track = [
+            0x00, 0xff, 0x51, 0x03, 0x07, 0xa1, 0x20,
+            0x00, 0x90, 60, 100,
+            0x60, 60, 0,
+        ];
+        let bytes = file(0, 96, &[&track]);
+        assert!(parse_midi(&bytes, "test").is_ok());
+        for end in 0..bytes.len() {
+            assert!(parse_midi(&bytes[..end], "test").is_err(), "{} bytes", end);
+        }
+    }
+
+    #[test]
+    fn wrong_tracks_are_errors() {
+        // a note cut in the middle of the track, an event without status,
+        // and a variable length quantity that does not end
+        for track in [&[0x00, 0x90, 60][..], &[0x00, 60, 100], &[0xff, 0xff, 0xff, 0xff, 0x00]] {
+            let mut bytes = file(0, 96, &[]);
+            bytes[11] = 1;
+            bytes.extend(b"MTrk");
+            bytes.extend((track.len() as u32).to_be_bytes());
+            bytes.extend(track);
+            assert!(parse_midi(&bytes, "test").is_err());
+        }
+        assert!(parse_midi(b"RIFF", "test").is_err());
+    }
+}
@@ -0,0 +1,309 @@
+use super::staff::{Key, staff_note};
+
+// the notes are quantised to sixteenths, and the durations are counted in sixteenths
+const DIVISIONS: usize = 4;
+// the notes from middle C are written on the treble staff, the others on the bass staff
+const MIDDLE_C: i32 = 60;
+
+static LETTERS: [&str; 7] = ["C", "D", "E", "F", "G", "A", "B"];
+
+// the durations that can be written with one note, in sixteenths, with their type and dot
+static DURATIONS: [(usize, &str, bool); 8] = [
+    (16, "whole", false),
+    (12, "half", true),
+    (8, "half", false),
+    (6, "quarter", true),
+    (4, "quarter", false),
+    (3, "eighth", true),
+    (2, "eighth", false),
+    (1, "16th", false),
+];
+
+// Krumhansl and Kessler key profiles, from C
+static MAJOR_PROFILE: [f32; 12] = [6.35, 2.23, 3.48, 2.33, 4.38, 4.09, 2.52, 5.19, 2.39, 3.66, 2.29, 2.88];
+static MINOR_PROFILE: [f32; 12] = [6.33, 2.68, 3.52, 5.38, 2.60, 3.53, 2.54, 4.75, 3.98, 2.69, 3.34, 3.17];
+
+// a note played during the session, with its midi number and its times in beats
+pub struct RecordedNote {
+    pub pitch: i32,
+    pub start: f32,
+    pub end: f32,
+}
+
+// a note quantised, in sixteenths
+struct Quantised {
+    pitch: i32,
+    start: usize,
+    end: usize,
+}
+
+fn correlation(a: &[f32; 12], b: impl Fn(usize) -> f32) -> f32 {
+    let mean_a = a.iter().sum::<f32>() / 12.;
+    let mean_b = (0..12).map(&b).sum::<f32>() / 12.;
+    let (mut ab, mut aa, mut bb) = (0., 0., 0.);
+    for (i, x) in a.iter().enumerate() {
+        let (x, y) = (x - mean_a, b(i) - mean_b);
+        ab += x * y;
+        aa += x * x;
+        bb += y * y;
+    }
+    ab / (aa * bb).sqrt().max(f32::EPSILON)
+}
+
+// The key of the notes, as the pitch class of its tonic from C and whether it is minor,
+// found by comparing how long each pitch class is played with the key profiles
+pub fn detect_key(notes: &[RecordedNote]) -> (i32, bool) {
+    let mut durations = [0.; 12];
+    for note in notes {
+        durations[note.pitch.rem_euclid(12) as usize] += note.end - note.start;
+    }
+
+    let mut best = (f32::NEG_INFINITY, 0, false);
+    for tonic in 0..12 {
+        for (profile, minor) in [(&MAJOR_PROFILE, false), (&MINOR_PROFILE, true)] {
+            let r = correlation(&durations, |i| profile[(i + 12 - tonic) % 12]);
+            if r > best.0 {
+                best = (r, tonic as i32, minor);
+            }
+        }
+    }
+
+    (best.1, best.2)
+}
+
+// the pieces of notes and rests that make a duration
+fn split_duration(mut duration: usize) -> Vec<usize> {
+    let mut pieces = Vec::new();
+    while duration > 0 {
+        let piece = DURATIONS.iter().map(|d| d.0).find(|&d| d <= duration).unwrap();
+        pieces.push(piece);
+        duration -= piece;
+    }
+    pieces
+}
+
+fn write_note(xml: &mut String, key: &Key, pitch: Option<i32>, chord: bool, duration: usize, tie: (bool, bool), staff: usize) {
+    let &(_, kind, dot) = DURATIONS.iter().find(|d| d.0 == duration).unwrap();
+
+    xml.push_str("      <note>\n");
+    if chord {
+        xml.push_str("        <chord/>\n");
+    }
+    match pitch {
+        Some(p) => {
+            let (step, spelling) = staff_note(key, p);
+            xml.push_str(&format!("        <pitch><step>{}</step>", LETTERS[step.rem_euclid(7) as usize]));
+            if spelling.accidental != 0 {
+                xml.push_str(&format!("<alter>{}</alter>", spelling.accidental));
+            }
+            xml.push_str(&format!("<octave>{}</octave></pitch>\n", step.div_euclid(7)));
+        }
+        None => xml.push_str("        <rest/>\n"),
+    }
+    xml.push_str(&format!("        <duration>{}</duration>\n", duration));
+    if tie.0 {
+        xml.push_str("        <tie type=\"stop\"/>\n");
+    }
+    if tie.1 {
+        xml.push_str("        <tie type=\"start\"/>\n");
+    }
+    // one voice per staff
+    xml.push_str(&format!("        <voice>{}</voice>\n", 1 + 4 * (staff - 1)));
+    xml.push_str(&format!("        <type>{}</type>\n", kind));
+    if dot {
+        xml.push_str("        <dot/>\n");
+    }
+    xml.push_str(&format!("        <staff>{}</staff>\n", staff));
+    if tie.0 || tie.1 {
+        xml.push_str("        <notations>");
+        if tie.0 {
+            xml.push_str("<tied type=\"stop\"/>");
+        }
+        if tie.1 {
+            xml.push_str("<tied type=\"start\"/>");
+        }
+        xml.push_str("</notations>\n");
+    }
+    xml.push_str("      </note>\n");
+}
+
+// The notes of one staff during one measure.
+// The time is cut each time a note starts or ends, and the notes lasting longer are tied.
+fn write_staff(xml: &mut String, key: &Key, notes: &[&Quantised], from: usize, to: usize, staff: usize) {
+    let mut cuts: Vec<usize> = notes.iter()
+        .flat_map(|n| [n.start, n.end])
+        .filter(|&t| t > from && t < to)
+        .chain([from, to])
+        .collect();
+    cuts.sort();
+    cuts.dedup();
+
+    for slice in cuts.windows(2) {
+        let mut start = slice[0];
+        for duration in split_duration(slice[1] - slice[0]) {
+            let end = start + duration;
+            let mut sounding: Vec<&&Quantised> = notes.iter().filter(|n| n.start <= start && n.end > start).collect();
+            sounding.sort_by_key(|n| n.pitch);
+
+            if sounding.is_empty() {
+                write_note(xml, key, None, false, duration, (false, false), staff);
+            }
+            for (i, note) in sounding.iter().enumerate() {
+                let tie = (note.start < start, note.end > end);
+                write_note(xml, key, Some(note.pitch), i > 0, duration, tie, staff);
+            }
+            start = end;
+        }
+    }
+}
+
+// Writes the notes as a MusicXML score for piano, in the detected key,
+// with `beats_per_bar` quarter notes in each measure
+pub fn to_musicxml(notes: &[RecordedNote], beats_per_bar: usize, title: &str) -> String {
+    let (tonic, minor) = detect_key(notes);
+    // a minor key has the signature of its relative major
+    let key = Key::major(if minor {(tonic + 3) % 12} else {tonic});
+
+    let quantise = |t: f32| (t.max(0.) * DIVISIONS as f32).round() as usize;
+    let notes: Vec<Quantised> = notes.iter()
+        .map(|n| {
+            let start = quantise(n.start);
+            Quantised {pitch: n.pitch, start, end: quantise(n.end).max(start + 1)}
+        })
+        .collect();
+
+    let measure_length = beats_per_bar * DIVISIONS;
+    let end = notes.iter().map(|n| n.end).max().unwrap_or(0);
+    let n_measures = end.div_ceil(measure_length).max(1);
+
+    let mut xml = String::new();
+    xml.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"no\"?>\n");
+    xml.push_str("<!DOCTYPE score-partwise PUBLIC \"-//Recordare//DTD MusicXML 4.0 Partwise//EN\" \"http://www.musicxml.org/dtds/partwise.dtd\">\n");
+    xml.push_str("<score-partwise version=\"4.0\">\n");
+    xml.push_str(&format!("  <work><work-title>{}</work-title></work>\n", title));
+    xml.push_str("  <part-list>\n    <score-part id=\"P1\"><part-name>Piano</part-name></score-part>\n  </part-list>\n");
+    xml.push_str("  <part id=\"P1\">\n");
+
+    for m in 0..n_measures {
+        let (from, to) = (m * measure_length, (m + 1) * measure_length);
+        xml.push_str(&format!("    <measure number=\"{}\">\n", m + 1));
+
+        if m == 0 {
+            xml.push_str("      <attributes>\n");
+            xml.push_str(&format!("        <divisions>{}</divisions>\n", DIVISIONS));
+            xml.push_str(&format!(
+                "        <key><fifths>{}</fifths><mode>{}</mode></key>\n",
+                key.signature, if minor {"minor"} else {"major"},
+            ));
+            xml.push_str(&format!("        <time><beats>{}</beats><beat-type>4</beat-type></time>\n", beats_per_bar));
+            xml.push_str("        <staves>2</staves>\n");
+            xml.push_str("        <clef number=\"1\"><sign>G</sign><line>2</line></clef>\n");
+            xml.push_str("        <clef number=\"2\"><sign>F</sign><line>4</line></clef>\n");
+            xml.push_str("      </attributes>\n");
+        }
+
+        let in_measure = |n: &&Quantised| n.start < to && n.end > from;
+        let treble: Vec<&Quantised> = notes.iter().filter(in_measure).filter(|n| n.pitch >= MIDDLE_C).collect();
+        let bass: Vec<&Quantised> = notes.iter().filter(in_measure).filter(|n| n.pitch < MIDDLE_C).collect();
+
+        write_staff(&mut xml, &key, &treble, from, to, 1);
+        xml.push_str(&format!("      <backup><duration>{}</duration></backup>\n", measure_length));
+        write_staff(&mut xml, &key, &bass, from, to, 2);
+
+        xml.push_str("    </measure>\n");
+    }
+
+    xml.push_str("  </part>\n</score-partwise>\n");
+    xml
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // the notes one after the other, each with its length in beats
+    fn melody(notes: &[(i32, f32)]) -> Vec<RecordedNote> {
+        let mut start = 0.;
+        notes.iter().map(|&(pitch, length)| {
+            start += length;
+            RecordedNote {pitch, start: start - length, end: start}
+        }).collect()
+    }
+
+    #[test]
+    fn key_of_a_c_major_scale() {
+        let notes = melody(&[(60, 2.), (62, 1.), (64, 2.), (65, 1.), (67, 2.), (69, 1.), (71, 1.), (72, 2.)]);
+        assert_eq!(detect_key(&notes), (0, false));
+    }
+
+    #[test]
+    fn key_of_an_a_minor_melody() {
+        let notes = melody(&[(57, 2.), (59, 1.), (60, 2.), (62, 1.), (64, 2.), (65, 1.), (68, 1.), (69, 2.), (64, 1.), (57, 2.)]);
+        assert_eq!(detect_key(&notes), (9, true));
+    }
+
+    #[test]
+    fn durations_are_split_into_written_notes() {
+        assert_eq!(split_duration(0), Vec::<usize>::new());
+        assert_eq!(split_duration(5), vec![4, 1]);
+        assert_eq!(split_duration(7), vec![6, 1]);
+        assert_eq!(split_duration(11), vec![8, 3]);
+        assert_eq!(split_duration(16), vec![16]);
+        assert_eq!(split_duration(20), vec![16, 4]);
+        for duration in 1..64 {
+            let pieces = split_duration(duration);
+            assert_eq!(pieces.iter().sum::<usize>(), duration);
+            assert!(pieces.iter().all(|p| DURATIONS.iter().any(|d| d.0 == *p)));
+        }
+    }
+
+    // what is written in each note of the score
+    fn notes(xml: &str) -> Vec<&str> {
+        xml.split("<note>").skip(1).map(|n| n.split("</note>").next().unwrap()).collect()
+    }
+
+    #[test]
+    fn note_across_the_bar_is_tied() {
+        // from the fourth beat of the first bar to the end of the first beat of the second
+        let xml = to_musicxml(&[RecordedNote {pitch: 60, start: 3., end: 5.}], 4, "test");
+
+        let measures: Vec<&str> = xml.split("<measure ").skip(1).collect();
+        assert_eq!(measures.len(), 2);
+
+        // a dotted half rest, then the note tied to the next bar, and a whole rest on the bass staff
+        let first = notes(measures[0]);
+        assert_eq!(first.len(), 3);
+        assert!(first[0].contains("<rest/>") && first[0].contains("<duration>12</duration>") && first[0].contains("<dot/>"));
+        assert!(first[1].contains("<step>C</step><octave>4</octave>") && first[1].contains("<tie type=\"start\"/>"));
+        assert!(!first[1].contains("<tie type=\"stop\"/>"));
+        assert!(first[2].contains("<rest/>") && first[2].contains("<staff>2</staff>"));
+
+        let second = notes(measures[1]);
+        assert!(second[0].contains("<duration>4</duration>") && second[0].contains("<tie type=\"stop\"/>"));
+        assert!(!second[0].contains("<tie type=\"start\"/>"));
+        assert!(second[1].contains("<rest/>") && second[1].contains("<duration>12</duration>"));
+    }
+
+    #[test]
+    fn note_elements_are_in_the_order_of_the_schema() {
+        // a chord held into the second bar, ending on a dotted quarter
+        let xml = to_musicxml(&[
+            RecordedNote {pitch: 60, start: 0., end: 5.5},
+            RecordedNote {pitch: 64, start: 0., end: 5.5},
+        ], 4, "test");
+
+        let order = ["<chord/>", "<pitch>", "<duration>", "<tie ", "<voice>", "<type>", "<dot/>", "<staff>", "<notations>"];
+        let note = notes(&xml).into_iter()
+            .find(|n| order.iter().all(|tag| n.contains(tag)))
+            .expect("a tied dotted note of a chord");
+        let positions: Vec<usize> = order.iter().map(|tag| note.find(tag).unwrap()).collect();
+        assert!(positions.windows(2).all(|p| p[0] < p[1]), "{}", note);
+    }
+
+    #[test]
+    fn minor_key_has_the_signature_of_its_relative_major() {
+        let notes = melody(&[(57, 2.), (60, 1.), (64, 2.), (68, 1.), (69, 2.)]);
+        let xml = to_musicxml(&notes, 4, "test");
+        assert!(xml.contains("<key><fifths>0</fifths><mode>minor</mode></key>"));
+    }
+}
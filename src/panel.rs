@@ -0,0 +1,303 @@
+use bevy::prelude::*;
+use bevy::window::PrimaryWindow;
+
+use super::{StringParams, MainString};
+
+const PANEL_WIDTH: f32 = 260.;
+const SLIDER_WIDTH: f32 = 140.;
+
+#[derive(Component)]
+pub struct Panel;
+
+#[derive(Clone, Copy)]
+pub enum Param {
+    C,
+    Dt,
+    Spring,
+    SolidFriction,
+    LiquidFriction,
+    Excitation,
+}
+
+static PARAMS: [Param; 6] = [
+    Param::C,
+    Param::Dt,
+    Param::Spring,
+    Param::SolidFriction,
+    Param::LiquidFriction,
+    Param::Excitation,
+];
+
+impl Param {
+    fn name(self) -> &'static str {
+        match self {
+            Param::C => "c",
+            Param::Dt => "dt",
+            Param::Spring => "spring",
+            Param::SolidFriction => "solid friction",
+            Param::LiquidFriction => "liquid friction",
+            Param::Excitation => "excitation",
+        }
+    }
+
+    // the ranges are chosen so that c*dt stays below dx,
+    // otherwise the simulation explodes
+    fn range(self) -> (f32, f32) {
+        match self {
+            Param::C => (20., 150.),
+            Param::Dt => (0.005, 0.02),
+            Param::Spring => (0., 40.),
+            Param::SolidFriction => (0., 150.),
+            Param::LiquidFriction => (0., 0.1),
+            Param::Excitation => (0., 0.15),
+        }
+    }
+
+    fn value(self, p: &StringParams) -> f32 {
+        match self {
+            Param::C => p.c,
+            Param::Dt => p.dt,
+            Param::Spring => p.spring_coeff,
+            Param::SolidFriction => p.solid_friction_coeff,
+            Param::LiquidFriction => p.liquid_friction_coeff,
+            Param::Excitation => p.excitation_coeff,
+        }
+    }
+
+    fn value_mut(self, p: &mut StringParams) -> &mut f32 {
+        match self {
+            Param::C => &mut p.c,
+            Param::Dt => &mut p.dt,
+            Param::Spring => &mut p.spring_coeff,
+            Param::SolidFriction => &mut p.solid_friction_coeff,
+            Param::LiquidFriction => &mut p.liquid_friction_coeff,
+            Param::Excitation => &mut p.excitation_coeff,
+        }
+    }
+
+    fn fraction(self, p: &StringParams) -> f32 {
+        let (min, max) = self.range();
+        (self.value(p) - min) / (max - min)
+    }
+
+    fn set_fraction(self, p: &mut StringParams, fraction: f32) {
+        let (min, max) = self.range();
+        *self.value_mut(p) = min + fraction.clamp(0., 1.) * (max - min);
+    }
+}
+
+#[derive(Clone, Copy)]
+pub enum Preset {
+    Default,
+    Steel,
+    Nylon,
+    Rubber,
+}
+
+impl Preset {
+    fn name(self) -> &'static str {
+        match self {
+            Preset::Default => "reset",
+            Preset::Steel => "steel",
+            Preset::Nylon => "nylon",
+            Preset::Rubber => "rubber",
+        }
+    }
+
+    // only the material changes: the length and the chord are kept
+    fn apply(self, p: &mut StringParams) {
+        let (c, spring, solid, liquid, excitation) = match self {
+            Preset::Default => {
+                let d = StringParams::default();
+                (d.c, d.spring_coeff, d.solid_friction_coeff, d.liquid_friction_coeff, d.excitation_coeff)
+            }
+            Preset::Steel => (140., 20., 80., 0.005, 0.04),
+            Preset::Nylon => (90., 10., 40., 0.02, 0.05),
+            Preset::Rubber => (40., 5., 20., 0.08, 0.08),
+        };
+        p.c = c;
+        p.dt = StringParams::default().dt;
+        p.spring_coeff = spring;
+        p.solid_friction_coeff = solid;
+        p.liquid_friction_coeff = liquid;
+        p.excitation_coeff = excitation;
+    }
+}
+
+#[derive(Component)]
+pub struct Slider(Param);
+
+#[derive(Component)]
+pub struct SliderFill(Param);
+
+#[derive(Component)]
+pub struct SliderLabel(Param);
+
+#[derive(Component)]
+pub struct PresetButton(Preset);
+
+pub fn create_panel(
+    mut commands: Commands,
+    ) {
+    let text_style : TextStyle = TextStyle {
+        color: Color::WHITE,
+        font_size: 14.,
+        font: Default::default(),
+    };
+
+    let panel = NodeBundle {
+        style: Style {
+            position_type: PositionType::Absolute,
+            top: Val::Px(5.),
+            right: Val::Px(5.),
+            width: Val::Px(PANEL_WIDTH),
+            flex_direction: FlexDirection::Column,
+            padding: UiRect::all(Val::Px(5.)),
+            row_gap: Val::Px(3.),
+            ..default()
+        },
+        background_color: Color::rgba(0.1, 0.1, 0.1, 0.8).into(),
+        ..default()
+    };
+
+    commands.spawn((panel, Panel)).with_children(|panel| {
+        for param in PARAMS {
+            let row = NodeBundle {
+                style: Style {
+                    justify_content: JustifyContent::SpaceBetween,
+                    align_items: AlignItems::Center,
+                    ..default()
+                },
+                ..default()
+            };
+
+            panel.spawn(row).with_children(|row| {
+                row.spawn((
+                    TextBundle::from_section(param.name(), text_style.clone()),
+                    SliderLabel(param),
+                ));
+
+                let track = NodeBundle {
+                    style: Style {
+                        width: Val::Px(SLIDER_WIDTH),
+                        height: Val::Px(10.),
+                        ..default()
+                    },
+                    background_color: Color::GRAY.into(),
+                    ..default()
+                };
+
+                row.spawn((track, Slider(param), Interaction::default())).with_children(|track| {
+                    let fill = NodeBundle {
+                        style: Style {
+                            height: Val::Percent(100.),
+                            ..default()
+                        },
+                        background_color: Color::WHITE.into(),
+                        ..default()
+                    };
+                    track.spawn((fill, SliderFill(param)));
+                });
+            });
+        }
+
+        let buttons = NodeBundle {
+            style: Style {
+                justify_content: JustifyContent::SpaceBetween,
+                ..default()
+            },
+            ..default()
+        };
+
+        panel.spawn(buttons).with_children(|buttons| {
+            for preset in [Preset::Steel, Preset::Nylon, Preset::Rubber, Preset::Default] {
+                let button = ButtonBundle {
+                    style: Style {
+                        padding: UiRect::axes(Val::Px(6.), Val::Px(2.)),
+                        ..default()
+                    },
+                    background_color: Color::DARK_GRAY.into(),
+                    ..default()
+                };
+
+                buttons.spawn((button, PresetButton(preset))).with_children(|button| {
+                    button.spawn(TextBundle::from_section(preset.name(), text_style.clone()));
+                });
+            }
+        });
+    });
+}
+
+pub fn toggle_panel(
+    keyboard_input: Res<Input<KeyCode>>,
+    mut panel: Query<&mut Visibility, With<Panel>>,
+    ) {
+    if !keyboard_input.just_pressed(KeyCode::Tab) {
+        return
+    }
+
+    for mut visible in &mut panel {
+        *visible = match *visible {
+            Visibility::Hidden => Visibility::Inherited,
+            _ => Visibility::Hidden,
+        };
+    }
+}
+
+pub fn slider_system(
+    window: Query<&Window, With<PrimaryWindow>>,
+    sliders: Query<(&Slider, &Interaction, &Node, &GlobalTransform)>,
+    mut string: Query<&mut StringParams, With<MainString>>,
+    ) {
+    let cursor = match window.get_single().ok().and_then(|w| w.cursor_position()) {
+        Some(c) => c,
+        None => return
+    };
+
+    let mut p = match string.get_single_mut() {
+        Ok(p) => p,
+        Err(_) => return
+    };
+
+    for (slider, interaction, node, transform) in &sliders {
+        if *interaction != Interaction::Pressed {
+            continue
+        }
+
+        // the transform of a node is at its center
+        let left = transform.translation().x - node.size().x / 2.;
+        slider.0.set_fraction(&mut p, (cursor.x - left) / node.size().x);
+    }
+}
+
+pub fn preset_system(
+    buttons: Query<(&PresetButton, &Interaction), Changed<Interaction>>,
+    mut string: Query<&mut StringParams, With<MainString>>,
+    ) {
+    for (button, interaction) in &buttons {
+        if *interaction == Interaction::Pressed {
+            for mut p in &mut string {
+                button.0.apply(&mut p);
+            }
+        }
+    }
+}
+
+pub fn update_sliders(
+    string: Query<&StringParams, With<MainString>>,
+    mut fills: Query<(&SliderFill, &mut Style)>,
+    mut labels: Query<(&SliderLabel, &mut Text)>,
+    ) {
+    let p = match string.get_single() {
+        Ok(p) => p,
+        Err(_) => return
+    };
+
+    for (fill, mut style) in &mut fills {
+        style.width = Val::Percent(100. * fill.0.fraction(p));
+    }
+
+    for (label, mut text) in &mut labels {
+        text.sections[0].value = format!("{} {:.3}", label.0.name(), label.0.value(p));
+    }
+}
@@ -0,0 +1,96 @@
+use bevy::prelude::*;
+
+use super::{NotePosition, Playing, BaseNote, HideNotes, N_OCTAVES};
+
+// at the bottom of the screen
+const OFFSET: Vec2 = Vec2::new(0., -325.);
+const WHITE_SIZE: Vec2 = Vec2::new(24., 60.);
+const BLACK_SIZE: Vec2 = Vec2::new(14., 36.);
+
+// half tones from A of the black keys
+static BLACK_KEYS: [usize; 5] = [1, 4, 6, 9, 11];
+
+#[derive(Component)]
+pub struct PianoKey {
+    position: usize,
+    black: bool,
+    rect: Rect,
+}
+
+impl PianoKey {
+    fn color(&self) -> Color {
+        if self.black {Color::BLACK} else {Color::WHITE}
+    }
+}
+
+// the note of the on-screen piano under this point
+pub fn key_at(keys: &Query<&PianoKey>, point: Vec2) -> Option<usize> {
+    // the black keys are above the white ones
+    keys.iter()
+        .filter(|k| k.rect.contains(point))
+        .max_by_key(|k| k.black)
+        .map(|k| k.position)
+}
+
+pub fn create_piano(
+    mut commands: Commands,
+    base_note: Res<BaseNote>,
+    old_keys: Query<Entity, With<PianoKey>>,
+) {
+    for e in &old_keys {
+        commands.entity(e).despawn();
+    }
+
+    // the white keys are next to each other,
+    // the black keys are on the boundary between two white keys
+    let mut x = 0.;
+    let mut keys = Vec::new();
+    for position in 0..12 * N_OCTAVES {
+        let black = BLACK_KEYS.contains(&((position + base_note.0) % 12));
+        let rect = if black {
+            Rect::from_center_size(
+                Vec2::new(x, (WHITE_SIZE.y - BLACK_SIZE.y) / 2.),
+                BLACK_SIZE,
+            )
+        }
+        else {
+            x += WHITE_SIZE.x;
+            Rect::from_center_size(Vec2::new(x - WHITE_SIZE.x / 2., 0.), WHITE_SIZE)
+        };
+        keys.push(PianoKey {position, black, rect});
+    }
+
+    let shift = OFFSET - Vec2::new(x / 2., 0.);
+    for mut key in keys {
+        key.rect = Rect::from_center_size(key.rect.center() + shift, key.rect.size());
+
+        let sprite = SpriteBundle {
+            sprite: Sprite {
+                color: key.color(),
+                // leave a gap between the white keys
+                custom_size: Some(key.rect.size() - Vec2::new(2., 0.)),
+                ..default()
+            },
+            transform: Transform::from_translation(
+                key.rect.center().extend(if key.black {0.2} else {0.1})
+            ),
+            ..default()
+        };
+
+        commands.spawn((sprite, key));
+    }
+}
+
+pub fn draw_piano(
+    hide: Res<HideNotes>,
+    notes: Query<(&NotePosition, &Playing)>,
+    mut keys: Query<(&PianoKey, &mut Sprite)>,
+) {
+    for (key, mut sprite) in &mut keys {
+        let playing = !hide.0 && notes.iter().any(|(x, p)| x.0 == key.position && p.0);
+        let color = if playing {NotePosition(key.position).note(0).color()} else {key.color()};
+        if sprite.color != color {
+            sprite.color = color;
+        }
+    }
+}
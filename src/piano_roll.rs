@@ -0,0 +1,90 @@
+use bevy::prelude::*;
+
+use super::{NoteEvent, NotePosition, HideNotes, N_OCTAVES};
+
+// the present is on the right side
+const OFFSET: Vec2 = Vec2::new(300., 0.);
+const WIDTH: f32 = 500.;
+const HEIGHT: f32 = 384.;
+
+// how many seconds are shown
+const DURATION: f32 = 10.;
+
+// a note played from `start` to `end`, in seconds since the start of the app
+struct PlayedNote {
+    position: usize,
+    start: f32,
+    end: Option<f32>,
+}
+
+#[derive(Resource, Default)]
+pub struct PianoRoll {
+    notes: Vec<PlayedNote>,
+}
+
+pub fn record_piano_roll(
+    time: Res<Time>,
+    hide: Res<HideNotes>,
+    mut note_events: EventReader<NoteEvent>,
+    mut roll: ResMut<PianoRoll>,
+) {
+    let now = time.elapsed_seconds();
+
+    // the hidden notes are not recorded, but the notes started before hiding still end
+    for event in note_events.read() {
+        let position = event.position.0;
+        if event.on {
+            if hide.0 {
+                continue
+            }
+            roll.notes.push(PlayedNote {position, start: now, end: None});
+        }
+        else if let Some(note) = roll.notes.iter_mut().rev().find(|n| n.position == position && n.end.is_none()) {
+            note.end = Some(now);
+        }
+    }
+
+    roll.notes.retain(|n| n.end.is_none_or(|end| end > now - DURATION));
+}
+
+pub fn draw_piano_roll(
+    mut gizmos: Gizmos,
+    time: Res<Time>,
+    roll: Res<PianoRoll>,
+) {
+    let now = time.elapsed_seconds();
+    let n_lanes = 12 * N_OCTAVES;
+    let lane_height = HEIGHT / n_lanes as f32;
+    let bottom_left = OFFSET - Vec2::new(WIDTH, HEIGHT) / 2.;
+
+    gizmos.rect_2d(OFFSET, 0., Vec2::new(WIDTH, HEIGHT), Color::DARK_GRAY);
+
+    // one line per octave
+    for octave in 1..N_OCTAVES {
+        let y = (12 * octave) as f32 * lane_height;
+        gizmos.line_2d(bottom_left + Vec2::new(0., y), bottom_left + Vec2::new(WIDTH, y), Color::DARK_GRAY);
+    }
+
+    // one tick per second
+    for s in 0..DURATION as usize {
+        let x = WIDTH - (s as f32 + now.fract()) / DURATION * WIDTH;
+        gizmos.line_2d(bottom_left + Vec2::new(x, 0.), bottom_left + Vec2::new(x, -5.), Color::GRAY);
+    }
+
+    let time_to_x = |t: f32| ((t - now + DURATION) / DURATION).clamp(0., 1.) * WIDTH;
+
+    for note in &roll.notes {
+        let left = time_to_x(note.start);
+        let right = time_to_x(note.end.unwrap_or(now));
+        let bottom = note.position as f32 * lane_height;
+
+        let size = Vec2::new((right - left).max(1.), lane_height - 2.);
+        let center = bottom_left + Vec2::new(left, bottom + 1.) + size / 2.;
+        let color = NotePosition(note.position).note(0).color();
+
+        gizmos.rect_2d(center, 0., size, color);
+        if size.x > 2. {
+            gizmos.rect_2d(center, 0., size - 2., color);
+        }
+    }
+}
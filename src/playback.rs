@@ -0,0 +1,323 @@
+use bevy::prelude::*;
+use bevy::window::FileDragAndDrop;
+
+use std::path::Path;
+
+use super::{PressNote, BaseNote, CommandLine, N_OCTAVES, NOTE_NAMES, BASE_MIDI};
+use super::midi::parse_midi;
+use super::abc::parse_abc;
+use super::chords::Chord;
+use super::text_input::TextEntry;
+
+const LABEL_OFFSET: Vec3 = Vec3::new(300., 290., 0.);
+
+// how far the song goes with page up and page down, in seconds
+const SEEK_STEP: f32 = 5.;
+const MIN_SPEED: f32 = 0.25;
+const MAX_SPEED: f32 = 4.;
+
+pub struct SongNote {
+    // in seconds
+    pub start: f32,
+    pub duration: f32,
+    // the midi number of the note
+    pub pitch: u8,
+    pub track: usize,
+}
+
+pub struct Track {
+    pub name: String,
+    pub muted: bool,
+}
+
+// A piece of music loaded from a file, ready to be played
+#[derive(Default)]
+pub struct Song {
+    pub name: String,
+    pub tracks: Vec<Track>,
+    // sorted by start
+    pub notes: Vec<SongNote>,
+    // the chord symbols written in the song, with their start
+    pub chords: Vec<(f32, Chord)>,
+}
+
+impl Song {
+    fn duration(&self) -> f32 {
+        self.notes.iter().map(|n| n.start + n.duration).fold(0., f32::max)
+    }
+}
+
+// reads a song, the format being given by the extension of the file
+pub fn load_song(path: &Path) -> Result<Song, String> {
+    let name = path.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+    let bytes = std::fs::read(path).map_err(|e| e.to_string())?;
+
+    match path.extension().and_then(|e| e.to_str()).map(|e| e.to_lowercase()).as_deref() {
+        Some("mid") | Some("midi") => parse_midi(&bytes, &name),
+        Some("abc") => parse_abc(&String::from_utf8_lossy(&bytes), &name),
+        _ => Err(format!("unknown format for {}", name)),
+    }
+}
+
+// The position of a note on the circle.
+// The notes out of the range of the circle are moved by octaves.
+pub fn circle_position(pitch: u8, base_note: usize) -> usize {
+    let mut relative = pitch as i32 - BASE_MIDI - base_note as i32;
+    while relative < 0 {
+        relative += 12;
+    }
+    while relative >= 12 * N_OCTAVES as i32 {
+        relative -= 12;
+    }
+    relative as usize
+}
+
+fn format_time(seconds: f32) -> String {
+    let seconds = seconds.max(0.) as usize;
+    format!("{}:{:02}", seconds / 60, seconds % 60)
+}
+
+// releases the sounding notes chosen by `which`
+fn release(sounding: &mut Vec<(f32, usize, usize)>, which: impl Fn(&(f32, usize, usize)) -> bool) -> Vec<PressNote> {
+    let mut presses = Vec::new();
+    sounding.retain(|s| {
+        if which(s) {
+            presses.push(PressNote {position: s.2, pressed: false});
+        }
+        !which(s)
+    });
+    presses
+}
+
+#[derive(Resource)]
+pub struct Playback {
+    song: Option<Song>,
+    playing: bool,
+    // in seconds from the start of the song
+    time: f32,
+    speed: f32,
+    selected: usize,
+    // the index of the next note to start
+    next: usize,
+    // the notes sounding, with their end, track and position on the circle
+    sounding: Vec<(f32, usize, usize)>,
+    error: Option<String>,
+}
+
+impl Default for Playback {
+    fn default() -> Self {
+        Playback {
+            song: None,
+            playing: false,
+            time: 0.,
+            speed: 1.,
+            selected: 0,
+            next: 0,
+            sounding: Vec::new(),
+            error: None,
+        }
+    }
+}
+
+impl Playback {
+    pub fn playing(&self) -> bool {
+        self.playing
+    }
+
+    pub fn open(&mut self, song: Song) -> Vec<PressNote> {
+        let presses = release(&mut self.sounding, |_| true);
+        self.song = Some(song);
+        self.playing = true;
+        self.time = 0.;
+        self.next = 0;
+        self.selected = 0;
+        self.error = None;
+        presses
+    }
+
+    // goes to another time in the song, the notes sounding then being played
+    fn seek(&mut self, time: f32, base_note: usize) -> Vec<PressNote> {
+        let mut presses = release(&mut self.sounding, |_| true);
+        let song = match &self.song {
+            Some(s) => s,
+            None => return presses
+        };
+
+        self.time = time.clamp(0., song.duration());
+        self.next = song.notes.partition_point(|n| n.start < self.time);
+
+        for note in &song.notes[..self.next] {
+            if self.playing && note.start + note.duration > self.time && !song.tracks[note.track].muted {
+                let position = circle_position(note.pitch, base_note);
+                self.sounding.push((note.start + note.duration, note.track, position));
+                presses.push(PressNote {position, pressed: true});
+            }
+        }
+
+        presses
+    }
+
+    fn label(&self) -> String {
+        if let Some(e) = &self.error {
+            return format!("song: {}", e)
+        }
+
+        let song = match &self.song {
+            Some(s) => s,
+            None => return "song: drop a midi or abc file here".to_string()
+        };
+
+        let state = if self.playing {"playing"} else {"paused"};
+        let mut text = format!(
+            "{}: {} {}/{}, speed {:.2}",
+            song.name, state, format_time(self.time), format_time(song.duration()), self.speed,
+        );
+        if let Some((_, chord)) = song.chords.iter().rev().find(|(start, _)| *start <= self.time) {
+            text += &format!("\nchord: {}", chord.symbol(&NOTE_NAMES));
+        }
+        text += "\ntracks:";
+        for (i, track) in song.tracks.iter().enumerate() {
+            let name = if track.muted {format!("({})", track.name)} else {track.name.clone()};
+            if i == self.selected {
+                text += &format!(" [{}]", name);
+            }
+            else {
+                text += &format!(" {}", name);
+            }
+        }
+        text
+    }
+}
+
+#[derive(Component)]
+pub struct PlaybackLabel;
+
+pub fn create_playback_label(
+    mut commands: Commands,
+    playback: Res<Playback>,
+) {
+    let text_style : TextStyle = TextStyle {
+        color: Color::GRAY,
+        font_size: 14.,
+        font: Default::default(),
+    };
+
+    let label = Text2dBundle {
+        text: Text::from_section(playback.label(), text_style),
+        transform: Transform::from_translation(LABEL_OFFSET),
+        ..default()
+    };
+
+    commands.spawn((label, PlaybackLabel));
+}
+
+// the song given on the command line
+pub fn open_song_from_args(
+    command_line: Res<CommandLine>,
+    mut playback: ResMut<Playback>,
+) {
+    let path = match command_line.files.first() {
+        Some(p) => p,
+        None => return
+    };
+
+    match load_song(Path::new(path)) {
+        Ok(song) => {playback.open(song);}
+        Err(e) => playback.error = Some(e),
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn playback_system(
+    time: Res<Time>,
+    keyboard_input: Res<Input<KeyCode>>,
+    base_note: Res<BaseNote>,
+    entry: Res<TextEntry>,
+    mut dropped: EventReader<FileDragAndDrop>,
+    mut playback: ResMut<Playback>,
+    mut presses: EventWriter<PressNote>,
+    mut label: Query<&mut Text, With<PlaybackLabel>>,
+) {
+    let p = &mut *playback;
+
+    for event in dropped.read() {
+        if let FileDragAndDrop::DroppedFile {path_buf, ..} = event {
+            match load_song(path_buf) {
+                Ok(song) => presses.send_batch(p.open(song)),
+                Err(e) => p.error = Some(e),
+            }
+        }
+    }
+
+    if p.song.is_some() && !entry.active() {
+        if keyboard_input.just_pressed(KeyCode::End) {
+            p.playing = !p.playing;
+            if !p.playing {
+                presses.send_batch(release(&mut p.sounding, |_| true));
+            }
+            else {
+                presses.send_batch(p.seek(p.time, base_note.0));
+            }
+        }
+        if keyboard_input.just_pressed(KeyCode::Home) {
+            presses.send_batch(p.seek(0., base_note.0));
+        }
+        if keyboard_input.just_pressed(KeyCode::PageUp) {
+            presses.send_batch(p.seek(p.time + SEEK_STEP, base_note.0));
+        }
+        if keyboard_input.just_pressed(KeyCode::PageDown) {
+            presses.send_batch(p.seek(p.time - SEEK_STEP, base_note.0));
+        }
+        if keyboard_input.just_pressed(KeyCode::Comma) {
+            p.speed = (p.speed / 1.25).max(MIN_SPEED);
+        }
+        if keyboard_input.just_pressed(KeyCode::Period) {
+            p.speed = (p.speed * 1.25).min(MAX_SPEED);
+        }
+        if keyboard_input.just_pressed(KeyCode::B) {
+            let n_tracks = p.song.as_ref().map_or(1, |s| s.tracks.len());
+            p.selected = (p.selected + 1) % n_tracks;
+        }
+        if keyboard_input.just_pressed(KeyCode::Slash) {
+            let selected = p.selected;
+            if let Some(track) = p.song.as_mut().and_then(|s| s.tracks.get_mut(selected)) {
+                track.muted = !track.muted;
+                if track.muted {
+                    presses.send_batch(release(&mut p.sounding, |s| s.1 == selected));
+                }
+            }
+        }
+    }
+
+    if p.playing {
+        if let Some(song) = &p.song {
+            p.time += time.delta_seconds() * p.speed;
+
+            let now = p.time;
+            presses.send_batch(release(&mut p.sounding, |s| s.0 <= now));
+
+            while let Some(note) = song.notes.get(p.next).filter(|n| n.start <= now) {
+                p.next += 1;
+                if song.tracks[note.track].muted || note.start + note.duration <= now {
+                    continue
+                }
+                let position = circle_position(note.pitch, base_note.0);
+                p.sounding.push((note.start + note.duration, note.track, position));
+                presses.send(PressNote {position, pressed: true});
+            }
+
+            if p.next == song.notes.len() && p.sounding.is_empty() {
+                p.playing = false;
+                p.time = 0.;
+                p.next = 0;
+            }
+        }
+    }
+
+    let value = p.label();
+    for mut text in &mut label {
+        if text.sections[0].value != value {
+            text.sections[0].value = value.clone();
+        }
+    }
+}
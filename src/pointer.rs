@@ -0,0 +1,183 @@
+use bevy::prelude::*;
+use bevy::window::PrimaryWindow;
+use bevy::utils::HashMap;
+
+use super::{NotePosition, PressNote, Latched};
+use super::piano::{PianoKey, key_at};
+use super::circle::{note_at, dot_position, DOT_RADIUS};
+
+// the touches have their own ids, this one is for the mouse
+const MOUSE_ID: u64 = u64::MAX;
+
+const LABEL_OFFSET: Vec3 = Vec3::new(-300., 230., 0.);
+
+// what happens when the dots of the circle are clicked
+#[derive(Resource, Default, Clone, Copy, PartialEq, Eq)]
+pub enum CircleMode {
+    // the note is played as long as it is pressed
+    #[default]
+    Hold,
+    // the note is switched on or off
+    Toggle,
+}
+
+impl CircleMode {
+    fn name(self) -> &'static str {
+        match self {
+            CircleMode::Hold => "click: hold",
+            CircleMode::Toggle => "click: toggle",
+        }
+    }
+}
+
+#[derive(Component)]
+pub struct CircleModeLabel;
+
+pub fn create_circle_mode_label(
+    mut commands: Commands,
+    mode: Res<CircleMode>,
+) {
+    let text_style : TextStyle = TextStyle {
+        color: Color::GRAY,
+        font_size: 14.,
+        font: Default::default(),
+    };
+
+    let label = Text2dBundle {
+        text: Text::from_section(mode.name(), text_style),
+        transform: Transform::from_translation(LABEL_OFFSET),
+        ..default()
+    };
+
+    commands.spawn((label, CircleModeLabel));
+}
+
+pub fn circle_mode_system(
+    keyboard_input: Res<Input<KeyCode>>,
+    mut mode: ResMut<CircleMode>,
+    mut latched: ResMut<Latched>,
+    mut presses: EventWriter<PressNote>,
+    mut label: Query<&mut Text, With<CircleModeLabel>>,
+) {
+    if !keyboard_input.just_pressed(KeyCode::F3) {
+        return
+    }
+
+    *mode = match *mode {
+        CircleMode::Hold => CircleMode::Toggle,
+        CircleMode::Toggle => CircleMode::Hold,
+    };
+
+    // leaving the toggle mode releases its notes
+    if *mode == CircleMode::Hold {
+        presses.send_batch(latched.circle.clear());
+    }
+
+    for mut text in &mut label {
+        text.sections[0].value = mode.name().to_string();
+    }
+}
+
+// the screen positions of the mouse (if clicked) and of the fingers
+fn pointers(
+    window: &Query<&Window, With<PrimaryWindow>>,
+    mouse: &Input<MouseButton>,
+    touches: &Touches,
+) -> Vec<(u64, Vec2)> {
+    let mut pointers: Vec<(u64, Vec2)> = touches.iter()
+        .map(|t| (t.id(), t.position()))
+        .collect();
+
+    if mouse.pressed(MouseButton::Left) {
+        if let Some(cursor) = window.get_single().ok().and_then(|w| w.cursor_position()) {
+            pointers.push((MOUSE_ID, cursor));
+        }
+    }
+
+    pointers
+}
+
+// Each pointer (the mouse or a finger) holds the note under it.
+// When it moves to another note, the first one is released,
+// so sliding on the keys or around the circle plays them one after the other.
+// In toggle mode, each dot of the circle reached by a pointer is switched.
+#[allow(clippy::too_many_arguments)]
+pub fn pointer_input_system(
+    window: Query<&Window, With<PrimaryWindow>>,
+    camera: Query<(&Camera, &GlobalTransform)>,
+    mouse: Res<Input<MouseButton>>,
+    touches: Res<Touches>,
+    keys: Query<&PianoKey>,
+    mode: Res<CircleMode>,
+    mut latched: ResMut<Latched>,
+    mut held: Local<HashMap<u64, usize>>,
+    mut over: Local<HashMap<u64, usize>>,
+    mut presses: EventWriter<PressNote>,
+) {
+    let (camera, camera_transform) = match camera.get_single() {
+        Ok(c) => c,
+        Err(_) => return
+    };
+
+    let mut now_held = HashMap::new();
+    let mut now_over = HashMap::new();
+
+    for (id, screen_position) in pointers(&window, &mouse, &touches) {
+        let point = match camera.viewport_to_world_2d(camera_transform, screen_position) {
+            Some(p) => p,
+            None => continue
+        };
+
+        if let Some(position) = key_at(&keys, point) {
+            now_held.insert(id, position);
+        }
+        else if let Some(position) = note_at(point) {
+            match *mode {
+                CircleMode::Hold => {
+                    now_held.insert(id, position);
+                }
+                CircleMode::Toggle => {
+                    now_over.insert(id, position);
+                    if over.get(&id) != Some(&position) {
+                        presses.send(latched.circle.toggle(position));
+                    }
+                }
+            }
+        }
+    }
+
+    for (id, &position) in held.iter() {
+        if now_held.get(id) != Some(&position) {
+            presses.send(PressNote {position, pressed: false});
+        }
+    }
+
+    for (id, &position) in now_held.iter() {
+        if held.get(id) != Some(&position) {
+            presses.send(PressNote {position, pressed: true});
+        }
+    }
+
+    *held = now_held;
+    *over = now_over;
+}
+
+// show where the hidden dots of the circle are
+pub fn draw_hover(
+    mut gizmos: Gizmos,
+    window: Query<&Window, With<PrimaryWindow>>,
+    camera: Query<(&Camera, &GlobalTransform)>,
+) {
+    let (camera, camera_transform) = match camera.get_single() {
+        Ok(c) => c,
+        Err(_) => return
+    };
+
+    let point = window.get_single().ok()
+        .and_then(|w| w.cursor_position())
+        .and_then(|cursor| camera.viewport_to_world_2d(camera_transform, cursor));
+
+    if let Some(position) = point.and_then(note_at) {
+        gizmos.circle_2d(dot_position(&NotePosition(position)), DOT_RADIUS + 3., Color::DARK_GRAY);
+    }
+}
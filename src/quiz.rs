@@ -0,0 +1,609 @@
+use bevy::prelude::*;
+use bevy::utils::HashSet;
+
+use super::{PressNote, HideNotes, BaseNote, NotePosition, Held, CommandLine, N_OCTAVES, NOTE_NAMES};
+use super::interval::interval_name;
+use super::chords::{CHORD_TYPES, parse_chord_type};
+use super::keyboard::CHORD_KEYS;
+use super::text_input::TextEntry;
+
+// below the interval label, and left of the piano and the text entry
+const LABEL_OFFSET: Vec3 = Vec3::new(-380., -285., 0.);
+// the answers are written on lines of this many, to keep the label narrow
+const ANSWERS_PER_LINE: usize = 6;
+
+// how long each step of a question is played, in seconds
+const STEP_DURATION: f32 = 0.8;
+// how long the answer is shown before the next question
+const ANSWER_DURATION: f32 = 2.;
+
+// the keys answering from a minor second to an octave, as on the number row
+static ANSWER_KEYS: [(KeyCode, &str, &str); 12] = [
+    (KeyCode::Key1, "1", "m2"), (KeyCode::Key2, "2", "M2"), (KeyCode::Key3, "3", "m3"),
+    (KeyCode::Key4, "4", "M3"), (KeyCode::Key5, "5", "P4"), (KeyCode::Key6, "6", "TT"),
+    (KeyCode::Key7, "7", "P5"), (KeyCode::Key8, "8", "m6"), (KeyCode::Key9, "9", "M6"),
+    (KeyCode::Key0, "0", "m7"), (KeyCode::Minus, "-", "M7"), (KeyCode::Equals, "=", "P8"),
+];
+
+// what is written on the keys of `CHORD_KEYS`
+static CHORD_KEY_NAMES: [&str; 11] = ["a", "s", "d", "f", "g", "h", "j", "k", "l", ";", "'"];
+
+static INVERSION_NAMES: [&str; 4] = ["root position", "first inversion", "second inversion", "third inversion"];
+
+// the chords asked when they are not given with `--quiz-chords` and `--quiz-inversions`
+static DEFAULT_CHORD_TYPES: [&str; 4] = ["major", "minor", "diminished", "augmented"];
+static DEFAULT_INVERSIONS: [usize; 1] = [0];
+
+// a chord is asked up to 2^MAX_LEVEL times more often than the ones well known
+const MAX_LEVEL: usize = 3;
+
+#[derive(Clone, Copy, PartialEq)]
+enum QuizKind {
+    // the number of half tones of an interval is given on the number row
+    Intervals,
+    // the quality of a chord is given with the keys of the chord mode
+    ChordQualities,
+    // the chord is played back, from the bass note given
+    ChordsToPlay,
+}
+
+impl QuizKind {
+    // Backslash goes through all of them, and then stops the quiz
+    fn next(kind: Option<Self>) -> Option<Self> {
+        match kind {
+            None => Some(QuizKind::Intervals),
+            Some(QuizKind::Intervals) => Some(QuizKind::ChordQualities),
+            Some(QuizKind::ChordQualities) => Some(QuizKind::ChordsToPlay),
+            Some(QuizKind::ChordsToPlay) => None,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            QuizKind::Intervals => "interval quiz",
+            QuizKind::ChordQualities => "chord quiz",
+            QuizKind::ChordsToPlay => "chord playing quiz",
+        }
+    }
+}
+
+#[derive(PartialEq)]
+enum Phase {
+    // the question is played, the notes being hidden
+    Asking,
+    // the notes of the answer are shown
+    Answered,
+}
+
+// A chord type in one of its inversions, with how well it is known.
+// The level goes up with each right answer and back to 0 with a wrong one,
+// the chords of the lowest levels being asked more often.
+struct ChordItem {
+    chord_type: usize,
+    inversion: usize,
+    level: usize,
+    asked: usize,
+    right: usize,
+}
+
+impl ChordItem {
+    fn name(&self) -> String {
+        match self.inversion {
+            0 => CHORD_TYPES[self.chord_type].name.to_string(),
+            i => format!("{}, {}", CHORD_TYPES[self.chord_type].name, INVERSION_NAMES[i]),
+        }
+    }
+
+    fn weight(&self) -> usize {
+        1 << (MAX_LEVEL - self.level)
+    }
+
+    // the half tones of the notes above the bass, the notes below it being moved up an octave
+    fn offsets(&self) -> Vec<usize> {
+        let intervals = CHORD_TYPES[self.chord_type].intervals;
+        let bass = intervals[self.inversion];
+        intervals[self.inversion..].iter().map(|i| i - bass)
+            .chain(intervals[..self.inversion].iter().map(|i| i + 12 - bass))
+            .collect()
+    }
+
+    // the half tones from the bass to the root, in the same octave
+    fn root_offset(&self) -> usize {
+        (12 - CHORD_TYPES[self.chord_type].intervals[self.inversion]) % 12
+    }
+}
+
+fn chord_items(chord_types: &[usize], inversions: &[usize]) -> Vec<ChordItem> {
+    let mut items = Vec::new();
+    for &chord_type in chord_types {
+        for &inversion in inversions.iter().filter(|&&i| i < CHORD_TYPES[chord_type].intervals.len()) {
+            items.push(ChordItem {chord_type, inversion, level: 0, asked: 0, right: 0});
+        }
+    }
+    items
+}
+
+// a chord type written as in a chord symbol, or with its name
+fn parse_quiz_chord_type(text: &str) -> Option<usize> {
+    parse_chord_type(text).or_else(|| CHORD_TYPES.iter().position(|t| t.name == text))
+}
+
+// what was answered right the least often, with its name, asked and right answers
+fn weakest(stats: Vec<(String, usize, usize)>) -> Option<(String, usize, usize)> {
+    stats.into_iter()
+        .filter(|s| s.1 > 0)
+        .min_by(|a, b| (a.2 as f32 / a.1 as f32).total_cmp(&(b.2 as f32 / b.1 as f32)))
+}
+
+fn answer_lines(answers: &[String]) -> String {
+    answers.chunks(ANSWERS_PER_LINE).map(|line| line.join("  ")).collect::<Vec<_>>().join("\n")
+}
+
+#[derive(Resource)]
+pub struct Quiz {
+    kind: Option<QuizKind>,
+    phase: Phase,
+    // since the start of the phase, in seconds
+    elapsed: f32,
+    // the positions of the notes asked, from the lowest
+    notes: Vec<usize>,
+    // the index in `chord_items` of the chord asked
+    item: usize,
+    sounding: Vec<usize>,
+    // the chord played back is read only once all the keys have been released
+    ready: bool,
+    // asked and right answers, by number of half tones minus one
+    interval_stats: [(usize, usize); 12],
+    chord_items: Vec<ChordItem>,
+    feedback: String,
+    random_state: u32,
+}
+
+impl Default for Quiz {
+    fn default() -> Self {
+        let chord_types: Vec<usize> = DEFAULT_CHORD_TYPES.iter().filter_map(|t| parse_quiz_chord_type(t)).collect();
+        Quiz {
+            kind: None,
+            phase: Phase::Asking,
+            elapsed: 0.,
+            notes: Vec::new(),
+            item: 0,
+            sounding: Vec::new(),
+            ready: false,
+            interval_stats: [(0, 0); 12],
+            chord_items: chord_items(&chord_types, &DEFAULT_INVERSIONS),
+            feedback: String::new(),
+            random_state: 0x9e3779b9,
+        }
+    }
+}
+
+impl Quiz {
+    pub fn active(&self) -> bool {
+        self.kind.is_some()
+    }
+
+    // the keys answer the quiz instead of playing notes
+    pub fn captures_keys(&self) -> bool {
+        matches!(self.kind, Some(QuizKind::Intervals) | Some(QuizKind::ChordQualities))
+    }
+
+    // xorshift, good enough to choose the questions
+    fn random(&mut self) -> usize {
+        let mut x = self.random_state;
+        x ^= x << 13;
+        x ^= x >> 17;
+        x ^= x << 5;
+        self.random_state = x;
+        x as usize
+    }
+
+    // the index of a chord item, the least known ones being the most likely
+    fn random_item(&mut self) -> usize {
+        let total: usize = self.chord_items.iter().map(|i| i.weight()).sum();
+        let mut r = self.random() % total;
+        for (i, item) in self.chord_items.iter().enumerate() {
+            if r < item.weight() {
+                return i
+            }
+            r -= item.weight();
+        }
+        0
+    }
+
+    fn ask(&mut self) {
+        self.phase = Phase::Asking;
+        self.elapsed = 0.;
+        self.ready = false;
+
+        self.notes = match self.kind {
+            Some(QuizKind::Intervals) => {
+                let root = self.random() % 12;
+                vec![root, root + 1 + self.random() % 12]
+            }
+            Some(_) => {
+                self.item = self.random_item();
+                let offsets = self.chord_items[self.item].offsets();
+                let span = offsets.last().copied().unwrap_or(0);
+                let bass = self.random() % (12 * N_OCTAVES - span);
+                offsets.iter().map(|o| bass + o).collect()
+            }
+            None => Vec::new(),
+        };
+    }
+
+    // the notes played at each step of the question
+    fn steps(&self) -> Vec<Vec<usize>> {
+        match self.kind {
+            // the lower note, the upper note and then both
+            Some(QuizKind::Intervals) => vec![vec![self.notes[0]], vec![self.notes[1]], self.notes.clone()],
+            // the chord, its notes from the bass, and the chord again
+            Some(_) => {
+                let mut steps = vec![self.notes.clone()];
+                steps.extend(self.notes.iter().map(|&n| vec![n]));
+                steps.push(self.notes.clone());
+                steps
+            }
+            None => Vec::new(),
+        }
+    }
+
+    // plays these notes instead of the ones sounding
+    fn sound(&mut self, positions: Vec<usize>) -> Vec<PressNote> {
+        let mut presses = self.release();
+        presses.extend(positions.iter().map(|&position| PressNote {position, pressed: true}));
+        self.sounding = positions;
+        presses
+    }
+
+    fn release(&mut self) -> Vec<PressNote> {
+        self.sounding.drain(..).map(|position| PressNote {position, pressed: false}).collect()
+    }
+
+    // the notes asked are played and shown
+    fn show_answer(&mut self, right: bool, answer: String) -> Vec<PressNote> {
+        self.feedback = if right {format!("right, {}", answer)} else {format!("no, it was {}", answer)};
+        self.phase = Phase::Answered;
+        self.elapsed = 0.;
+        self.sound(self.notes.clone())
+    }
+
+    fn answer_interval(&mut self, half_tones: usize) -> Vec<PressNote> {
+        let asked = self.notes[1] - self.notes[0];
+        let right = half_tones == asked;
+        let stat = &mut self.interval_stats[asked - 1];
+        stat.0 += 1;
+        if right {
+            stat.1 += 1;
+        }
+
+        let answer = if right {interval_name(asked)} else {format!("{}, not {}", interval_name(asked), interval_name(half_tones))};
+        self.show_answer(right, answer)
+    }
+
+    fn answer_chord(&mut self, right: bool, answer: String) -> Vec<PressNote> {
+        let item = &mut self.chord_items[self.item];
+        item.asked += 1;
+        if right {
+            item.right += 1;
+            item.level = (item.level + 1).min(MAX_LEVEL);
+        }
+        else {
+            item.level = 0;
+        }
+        self.show_answer(right, answer)
+    }
+
+    fn answer_quality(&mut self, chord_type: usize) -> Vec<PressNote> {
+        let item = &self.chord_items[self.item];
+        let right = chord_type == item.chord_type;
+        let answer = if right {item.name()} else {format!("{}, not {}", item.name(), CHORD_TYPES[chord_type].name)};
+        self.answer_chord(right, answer)
+    }
+
+    // The chord played back is right when it has the same notes, in any octave,
+    // over the same bass. It is read as soon as enough different notes are held.
+    fn answer_played(&mut self, held: &[usize], base_note: usize) -> Vec<PressNote> {
+        let played: HashSet<usize> = held.iter().map(|p| p % 12).collect();
+        if !self.ready || played.len() < self.notes.len() {
+            self.ready |= held.is_empty();
+            return Vec::new()
+        }
+
+        let asked: HashSet<usize> = self.notes.iter().map(|p| p % 12).collect();
+        let bass = held.iter().min().map(|p| p % 12);
+        let right = played == asked && bass == Some(self.notes[0] % 12);
+
+        let item = &self.chord_items[self.item];
+        let root = NOTE_NAMES[(base_note + self.notes[0] + item.root_offset()) % 12];
+        let answer = format!("{} {}", root, item.name());
+        self.answer_chord(right, answer)
+    }
+
+    fn label(&self, base_note: usize) -> String {
+        let kind = match self.kind {
+            Some(k) => k,
+            None => return String::new()
+        };
+
+        let stats: Vec<(String, usize, usize)> = match kind {
+            QuizKind::Intervals => self.interval_stats.iter().enumerate()
+                .map(|(i, s)| (interval_name(i + 1), s.0, s.1))
+                .collect(),
+            _ => self.chord_items.iter()
+                .map(|i| (i.name(), i.asked, i.right))
+                .collect(),
+        };
+
+        let (asked, right) = stats.iter().fold((0, 0), |t, s| (t.0 + s.1, t.1 + s.2));
+        let mut text = format!("{}: {}/{} right", kind.name(), right, asked);
+        if let Some((name, asked, right)) = weakest(stats) {
+            text += &format!(", weakest {} {}/{}", name, right, asked);
+        }
+
+        if self.phase == Phase::Answered {
+            return format!("{}\n{}", text, self.feedback)
+        }
+
+        match kind {
+            QuizKind::Intervals => {
+                text += "\nwhich interval? (return plays it again)\n";
+                let answers: Vec<String> = ANSWER_KEYS.iter().map(|(_, k, n)| format!("{} {}", k, n)).collect();
+                text += &answer_lines(&answers);
+            }
+            QuizKind::ChordQualities => {
+                text += "\nwhich chord? (return plays it again)\n";
+                let mut chord_types: Vec<usize> = self.chord_items.iter().map(|i| i.chord_type).collect();
+                chord_types.dedup();
+                let answers: Vec<String> = chord_types.iter()
+                    .map(|&t| format!("{} {}", CHORD_KEY_NAMES[t], CHORD_TYPES[t].name))
+                    .collect();
+                text += &answer_lines(&answers);
+            }
+            QuizKind::ChordsToPlay => {
+                let bass = NOTE_NAMES[(base_note + self.notes[0]) % 12];
+                text += &format!("\nplay this chord over {} (return plays it again)", bass);
+            }
+        }
+        text
+    }
+}
+
+// the chords asked, given like `--quiz-chords major,m7,dim --quiz-inversions 0,1`
+pub fn quiz_from_args(
+    command_line: Res<CommandLine>,
+    mut quiz: ResMut<Quiz>,
+) {
+    let chord_types: Vec<usize> = match command_line.option("quiz-chords") {
+        Some(text) => text.split(',')
+            .map(|t| t.trim())
+            .filter_map(|t| {
+                let chord_type = parse_quiz_chord_type(t);
+                if chord_type.is_none() {
+                    warn!("unknown chord type \"{}\"", t);
+                }
+                chord_type
+            })
+            .collect(),
+        None => DEFAULT_CHORD_TYPES.iter().filter_map(|t| parse_quiz_chord_type(t)).collect(),
+    };
+
+    let inversions: Vec<usize> = match command_line.option("quiz-inversions") {
+        Some(text) => text.split(',')
+            .map(|i| i.trim())
+            .filter_map(|i| {
+                let inversion = i.parse().ok().filter(|&i: &usize| i < INVERSION_NAMES.len());
+                if inversion.is_none() {
+                    warn!("unknown inversion \"{}\"", i);
+                }
+                inversion
+            })
+            .collect(),
+        None => DEFAULT_INVERSIONS.to_vec(),
+    };
+
+    let items = chord_items(&chord_types, &inversions);
+    if items.is_empty() {
+        warn!("no chord to ask, the default ones are used");
+        return
+    }
+    quiz.chord_items = items;
+}
+
+#[derive(Component)]
+pub struct QuizLabel;
+
+pub fn create_quiz_label(
+    mut commands: Commands,
+) {
+    let text_style : TextStyle = TextStyle {
+        color: Color::WHITE,
+        font_size: 14.,
+        font: Default::default(),
+    };
+
+    let label = Text2dBundle {
+        text: Text::from_section("", text_style),
+        transform: Transform::from_translation(LABEL_OFFSET),
+        ..default()
+    };
+
+    commands.spawn((label, QuizLabel));
+}
+
+// Backslash goes from one quiz to the next, and then stops
+#[allow(clippy::too_many_arguments)]
+pub fn quiz_system(
+    time: Res<Time>,
+    keyboard_input: Res<Input<KeyCode>>,
+    base_note: Res<BaseNote>,
+    entry: Res<TextEntry>,
+    notes: Query<(&NotePosition, &Held)>,
+    mut quiz: ResMut<Quiz>,
+    mut hide: ResMut<HideNotes>,
+    mut presses: EventWriter<PressNote>,
+    mut label: Query<&mut Text, With<QuizLabel>>,
+) {
+    let q = &mut *quiz;
+
+    if keyboard_input.just_pressed(KeyCode::Backslash) && !entry.active() {
+        q.kind = QuizKind::next(q.kind);
+        presses.send_batch(q.release());
+        if q.kind.is_some() {
+            q.random_state ^= time.elapsed().subsec_nanos() | 1;
+            q.ask();
+        }
+    }
+
+    if let Some(kind) = q.kind {
+        q.elapsed += time.delta_seconds();
+
+        match q.phase {
+            Phase::Asking => {
+                if keyboard_input.just_pressed(KeyCode::Return) && !entry.active() {
+                    q.elapsed = 0.;
+                }
+
+                let step = (q.elapsed / STEP_DURATION) as usize;
+                match q.steps().into_iter().nth(step) {
+                    Some(positions) if positions != q.sounding => presses.send_batch(q.sound(positions)),
+                    Some(_) => {}
+                    None => presses.send_batch(q.release()),
+                }
+
+                if !entry.active() {
+                    match kind {
+                        QuizKind::Intervals => {
+                            if let Some(i) = ANSWER_KEYS.iter().position(|&(k, ..)| keyboard_input.just_pressed(k)) {
+                                presses.send_batch(q.answer_interval(i + 1));
+                            }
+                        }
+                        QuizKind::ChordQualities => {
+                            if let Some(t) = CHORD_KEYS.iter().position(|&k| keyboard_input.just_pressed(k)) {
+                                presses.send_batch(q.answer_quality(t));
+                            }
+                        }
+                        // once the question has been played, the notes held are the ones played back
+                        QuizKind::ChordsToPlay => {
+                            if q.sounding.is_empty() {
+                                let held: Vec<usize> = notes.iter().filter(|(_, h)| h.0 > 0).map(|(p, _)| p.0).collect();
+                                presses.send_batch(q.answer_played(&held, base_note.0));
+                            }
+                        }
+                    }
+                }
+            }
+            Phase::Answered => {
+                if q.elapsed >= ANSWER_DURATION {
+                    presses.send_batch(q.release());
+                    q.ask();
+                }
+            }
+        }
+    }
+
+    // the notes are shown again once the question has been played, to see the ones played back
+    let hidden = q.phase == Phase::Asking && !q.sounding.is_empty();
+    if hide.0 != hidden {
+        hide.0 = hidden;
+    }
+
+    let value = q.label(base_note.0);
+    for mut text in &mut label {
+        if text.sections[0].value != value {
+            text.sections[0].value = value.clone();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn item(chord_type: &str, inversion: usize) -> ChordItem {
+        let chord_type = parse_quiz_chord_type(chord_type).unwrap();
+        ChordItem {chord_type, inversion, level: 0, asked: 0, right: 0}
+    }
+
+    // the chord playing quiz on these notes, asking the first default chord: major in root position
+    fn quiz(notes: Vec<usize>) -> Quiz {
+        Quiz {kind: Some(QuizKind::ChordsToPlay), notes, ..default()}
+    }
+
+    #[test]
+    fn inversions_are_voiced_above_their_bass() {
+        assert_eq!(item("major", 0).offsets(), vec![0, 4, 7]);
+        assert_eq!(item("major", 1).offsets(), vec![0, 3, 8]);
+        assert_eq!(item("major", 2).offsets(), vec![0, 5, 9]);
+        assert_eq!(item("m7", 3).offsets(), vec![0, 2, 5, 9]);
+
+        assert_eq!(item("major", 0).root_offset(), 0);
+        assert_eq!(item("major", 1).root_offset(), 8);
+        assert_eq!(item("major", 2).root_offset(), 5);
+        assert_eq!(item("m7", 3).root_offset(), 2);
+    }
+
+    #[test]
+    fn missing_inversions_are_not_asked() {
+        let triads = [parse_quiz_chord_type("major").unwrap(), parse_quiz_chord_type("minor").unwrap()];
+        let sevenths = [parse_quiz_chord_type("7").unwrap()];
+        assert_eq!(chord_items(&triads, &[0, 3]).len(), 2);
+        assert_eq!(chord_items(&sevenths, &[0, 3]).len(), 2);
+        assert_eq!(parse_quiz_chord_type("dominant seventh"), Some(sevenths[0]));
+    }
+
+    #[test]
+    fn weak_chords_are_asked_more_often() {
+        let mut known = item("minor", 0);
+        known.level = MAX_LEVEL;
+        assert_eq!(item("major", 0).weight(), 1 << MAX_LEVEL);
+        assert_eq!(known.weight(), 1);
+
+        let mut q = Quiz {chord_items: vec![item("major", 0), known], ..default()};
+        let mut counts = [0; 2];
+        for _ in 0..9000 {
+            counts[q.random_item()] += 1;
+        }
+        assert!(counts[0] > 6 * counts[1], "{:?}", counts);
+        assert!(counts[1] > 0);
+    }
+
+    #[test]
+    fn chord_played_in_another_octave_is_right() {
+        let mut q = quiz(vec![3, 7, 10]);
+
+        // the keys held before the question are not read
+        assert!(q.answer_played(&[3, 7, 10], 0).is_empty());
+        assert!(q.phase == Phase::Asking);
+
+        assert!(q.answer_played(&[], 0).is_empty());
+        // not enough notes yet
+        assert!(q.answer_played(&[15, 19], 0).is_empty());
+        assert!(q.phase == Phase::Asking);
+
+        q.answer_played(&[15, 19, 22, 27], 0);
+        assert!(q.phase == Phase::Answered);
+        assert_eq!(q.feedback, "right, do major");
+        assert_eq!(q.chord_items[0].level, 1);
+        assert_eq!((q.chord_items[0].asked, q.chord_items[0].right), (1, 1));
+    }
+
+    #[test]
+    fn chord_played_over_another_bass_is_wrong() {
+        let mut q = quiz(vec![3, 7, 10]);
+        q.chord_items[0].level = 2;
+        q.answer_played(&[], 0);
+
+        q.answer_played(&[7, 10, 15], 0);
+        assert_eq!(q.feedback, "no, it was do major");
+        assert_eq!(q.chord_items[0].level, 0);
+        assert_eq!((q.chord_items[0].asked, q.chord_items[0].right), (1, 0));
+
+        // the name of the root follows the base note and the inversion
+        let mut q = quiz(vec![7, 10, 15]);
+        q.chord_items[0].inversion = 1;
+        q.answer_played(&[], 2);
+        q.answer_played(&[7, 10, 14], 2);
+        assert_eq!(q.feedback, "no, it was re major, first inversion");
+    }
+}
@@ -0,0 +1,144 @@
+use bevy::prelude::*;
+
+use super::{Note, Playing, HideNotes};
+use super::sound::{Tap, SAMPLE_RATE, TAP_LENGTH};
+use super::spectrum::spectrum;
+
+const OFFSET: Vec2 = Vec2::new(300., 0.);
+const WIDTH: f32 = 500.;
+const HEIGHT: f32 = 160.;
+
+// centers of the two graphs
+const WAVE_CENTER: Vec2 = Vec2::new(0., 110.);
+const SPECTRUM_CENTER: Vec2 = Vec2::new(0., -110.);
+
+// number of samples shown on the oscilloscope
+const WAVE_SAMPLES: usize = 800;
+// number of samples analysed, must be a power of 2
+const SPECTRUM_SAMPLES: usize = 4096;
+// frequency range of the spectrum, drawn in log scale
+const MIN_FREQ: f32 = 40.;
+const MAX_FREQ: f32 = 4000.;
+// the spectrum is drawn in log scale, between 10^-DECADES and 1
+const DECADES: f32 = 3.;
+
+const N_LABELS: usize = 6;
+// a peak smaller than this fraction of the highest one is not labelled
+const PEAK_THRESHOLD: f32 = 0.2;
+
+#[derive(Component)]
+pub struct ScopeLabel;
+
+pub fn create_scope(
+    mut commands: Commands,
+) {
+    let text_style : TextStyle = TextStyle {
+        color: Color::WHITE,
+        font_size: 14.,
+        font: Default::default(),
+    };
+
+    for _ in 0..N_LABELS {
+        let label = Text2dBundle {
+            text: Text::from_section("", text_style.clone()),
+            visibility: Visibility::Hidden,
+            ..default()
+        };
+        commands.spawn((label, ScopeLabel));
+    }
+}
+
+pub fn clear_scope(
+    mut commands: Commands,
+    labels: Query<Entity, With<ScopeLabel>>,
+) {
+    for e in &labels {
+        commands.entity(e).despawn();
+    }
+}
+
+// position of a frequency on the horizontal axis, between 0 and 1
+fn freq_to_x(f: f32) -> f32 {
+    (f / MIN_FREQ).ln() / (MAX_FREQ / MIN_FREQ).ln()
+}
+
+pub fn draw_scope(
+    mut gizmos: Gizmos,
+    hide: Res<HideNotes>,
+    voices: Query<(&Tap, &Playing)>,
+    mut labels: Query<(&mut Text, &mut Transform, &mut Visibility), With<ScopeLabel>>,
+) {
+    let wave_center = OFFSET + WAVE_CENTER;
+    let spectrum_center = OFFSET + SPECTRUM_CENTER;
+    let size = Vec2::new(WIDTH, HEIGHT);
+    gizmos.rect_2d(wave_center, 0., size, Color::DARK_GRAY);
+    gizmos.rect_2d(spectrum_center, 0., size, Color::DARK_GRAY);
+
+    // mix all the voices being played
+    let mut mix = vec![0.; TAP_LENGTH];
+    for (tap, playing) in &voices {
+        if playing.0 {
+            for (m, x) in mix.iter_mut().zip(tap.last(TAP_LENGTH)) {
+                *m += x;
+            }
+        }
+    }
+
+    // start the wave on a rising edge, so that it does not move around
+    let search = TAP_LENGTH - WAVE_SAMPLES;
+    let start = (1..search)
+        .rev()
+        .find(|&i| mix[i-1] < 0. && mix[i] >= 0.)
+        .unwrap_or(search);
+
+    let max_amplitude = mix.iter().fold(0.5f32, |m, x| m.max(x.abs()));
+    gizmos.linestrip_2d(
+        mix[start..start + WAVE_SAMPLES].iter().enumerate().map(|(i, x)| wave_center + Vec2::new(
+            (i as f32 / WAVE_SAMPLES as f32 - 0.5) * WIDTH,
+            x / max_amplitude * HEIGHT / 2.,
+        )),
+        Color::GREEN,
+    );
+
+    let magnitudes = spectrum(&mix[TAP_LENGTH - SPECTRUM_SAMPLES..]);
+    let bin_width = SAMPLE_RATE as f32 / SPECTRUM_SAMPLES as f32;
+    let max_magnitude = magnitudes.iter().fold(f32::EPSILON, |m, &x| m.max(x));
+
+    let y = |m: f32| ((1. + (m / max_magnitude).max(f32::MIN_POSITIVE).log10() / DECADES) * HEIGHT)
+        .clamp(0., HEIGHT) - HEIGHT / 2.;
+
+    gizmos.linestrip_2d(
+        magnitudes.iter().enumerate()
+            .map(|(k, &m)| (k as f32 * bin_width, m))
+            .filter(|&(f, _)| (MIN_FREQ..MAX_FREQ).contains(&f))
+            .map(|(f, m)| spectrum_center + Vec2::new((freq_to_x(f) - 0.5) * WIDTH, y(m))),
+        Color::CYAN,
+    );
+
+    // the highest local maxima
+    let mut peaks: Vec<(usize, f32)> = (1..magnitudes.len()-1)
+        .filter(|&k| magnitudes[k] > magnitudes[k-1] && magnitudes[k] >= magnitudes[k+1])
+        .filter(|&k| magnitudes[k] > PEAK_THRESHOLD * max_magnitude)
+        .map(|k| (k, magnitudes[k]))
+        .collect();
+    peaks.sort_by(|a, b| b.1.total_cmp(&a.1));
+
+    // the names of the peaks would give the hidden notes away
+    let mut peaks = peaks.into_iter()
+        .map(|(k, m)| (k as f32 * bin_width, m))
+        .filter(|&(f, _)| (MIN_FREQ..MAX_FREQ).contains(&f) && !hide.0);
+
+    for (mut text, mut transform, mut visible) in &mut labels {
+        match peaks.next() {
+            Some((f, m)) => {
+                let note = Note::from_freq(f);
+                text.sections[0].value = note.name().to_string();
+                text.sections[0].style.color = note.color();
+                let x = (freq_to_x(f) - 0.5) * WIDTH;
+                transform.translation = (spectrum_center + Vec2::new(x, y(m) + 10.)).extend(0.);
+                *visible = Visibility::Visible;
+            }
+            None => *visible = Visibility::Hidden,
+        }
+    }
+}
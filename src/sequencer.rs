@@ -0,0 +1,226 @@
+use bevy::prelude::*;
+
+use super::{PressNote, BaseNote, NOTE_NAMES};
+use super::chords::{Chord, parse_chord_symbol, parse_roman_numeral};
+use super::text_input::{TextEntry, TextTarget, TextSubmitted};
+use super::transport::Transport;
+
+const LABEL_OFFSET: Vec3 = Vec3::new(-300., 290., 0.);
+
+// a chord lasts one bar of 4 beats, unless written otherwise
+const DEFAULT_BEATS: f32 = 4.;
+
+// Plays a chord progression in a loop, in time with the transport
+#[derive(Resource, Default)]
+pub struct Sequencer {
+    // the chords, with their duration in beats
+    steps: Vec<(Chord, f32)>,
+    playing: bool,
+    // the chord being played
+    index: Option<usize>,
+    // the position of the transport when the next chord starts
+    next: f32,
+    sounding: Vec<usize>,
+    error: Option<String>,
+}
+
+impl Sequencer {
+    pub fn playing(&self) -> bool {
+        self.playing
+    }
+
+    // the first chord starts on the next beat
+    fn start(&mut self, transport: &mut Transport) {
+        if !transport.playing() {
+            transport.start();
+        }
+        self.playing = true;
+        self.index = None;
+        self.next = transport.position().ceil();
+        self.error = None;
+    }
+
+    fn release(&mut self) -> Vec<PressNote> {
+        self.sounding.drain(..).map(|position| PressNote {position, pressed: false}).collect()
+    }
+
+    fn press(&mut self, base_note: usize) -> Vec<PressNote> {
+        let mut presses = self.release();
+        if let Some((chord, _)) = self.index.and_then(|i| self.steps.get(i)) {
+            self.sounding = chord.positions(base_note);
+            presses.extend(self.sounding.iter().map(|&position| PressNote {position, pressed: true}));
+        }
+        presses
+    }
+
+    fn label(&self) -> String {
+        if let Some(e) = &self.error {
+            return format!("sequencer: {}", e)
+        }
+        if !self.playing {
+            return "sequencer: stopped".to_string()
+        }
+        match self.index {
+            Some(i) => format!("sequencer: {} ({}/{})", self.steps[i].0.symbol(&NOTE_NAMES), i + 1, self.steps.len()),
+            None => "sequencer: ready".to_string(),
+        }
+    }
+}
+
+// the chords with their duration in beats, and the tempo if given
+type Progression = (Vec<(Chord, f32)>, Option<f32>);
+
+// A progression is made of chords separated by spaces (or bars),
+// each of them followed by its duration in beats, like "Am:2".
+// The chords are either chord symbols or roman numerals in the key of `tonic`.
+// The tempo is given like "@120".
+pub fn parse_progression(text: &str, tonic: usize) -> Result<Progression, String> {
+    let mut steps = Vec::new();
+    let mut bpm = None;
+
+    for token in text.split(|c: char| c.is_whitespace() || c == '|').filter(|t| !t.is_empty()) {
+        if let Some(tempo) = token.strip_prefix('@') {
+            bpm = Some(tempo.parse::<f32>().ok().filter(|&b| b > 0.).ok_or(format!("wrong tempo \"{}\"", tempo))?);
+            continue
+        }
+
+        let (symbol, beats) = match token.split_once(':') {
+            Some((symbol, beats)) => {
+                let beats = beats.parse::<f32>().ok().filter(|&b| b > 0.).ok_or(format!("wrong duration \"{}\"", beats))?;
+                (symbol, beats)
+            }
+            None => (token, DEFAULT_BEATS),
+        };
+
+        let chord = parse_chord_symbol(symbol)
+            .or_else(|| parse_roman_numeral(symbol, tonic))
+            .ok_or(format!("unknown chord \"{}\"", symbol))?;
+
+        steps.push((chord, beats));
+    }
+
+    if steps.is_empty() {
+        return Err("no chord".to_string())
+    }
+
+    Ok((steps, bpm))
+}
+
+#[derive(Component)]
+pub struct SequencerLabel;
+
+pub fn create_sequencer_label(
+    mut commands: Commands,
+    sequencer: Res<Sequencer>,
+) {
+    let text_style : TextStyle = TextStyle {
+        color: Color::GRAY,
+        font_size: 14.,
+        font: Default::default(),
+    };
+
+    let label = Text2dBundle {
+        text: Text::from_section(sequencer.label(), text_style),
+        transform: Transform::from_translation(LABEL_OFFSET),
+        ..default()
+    };
+
+    commands.spawn((label, SequencerLabel));
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn sequencer_system(
+    keyboard_input: Res<Input<KeyCode>>,
+    base_note: Res<BaseNote>,
+    mut entry: ResMut<TextEntry>,
+    mut submitted: EventReader<TextSubmitted>,
+    mut sequencer: ResMut<Sequencer>,
+    mut transport: ResMut<Transport>,
+    mut presses: EventWriter<PressNote>,
+    mut label: Query<&mut Text, With<SequencerLabel>>,
+) {
+    let s = &mut *sequencer;
+    let mut changed = false;
+
+    if keyboard_input.just_pressed(KeyCode::F10) && !entry.active() {
+        entry.open(TextTarget::Progression);
+    }
+
+    for text in submitted.read().filter(|t| t.target == TextTarget::Progression) {
+        match parse_progression(&text.text, base_note.0 % 12) {
+            Ok((steps, bpm)) => {
+                presses.send_batch(s.release());
+                s.steps = steps;
+                if let Some(bpm) = bpm {
+                    transport.set_bpm(bpm);
+                }
+                s.start(&mut transport);
+            }
+            Err(e) => s.error = Some(e),
+        }
+        changed = true;
+    }
+
+    if keyboard_input.just_pressed(KeyCode::F11) && !s.steps.is_empty() && !entry.active() {
+        if s.playing {
+            s.playing = false;
+            presses.send_batch(s.release());
+        }
+        else {
+            s.start(&mut transport);
+        }
+        changed = true;
+    }
+
+    // stopping the transport stops the sequencer
+    if s.playing && !transport.playing() {
+        s.playing = false;
+        presses.send_batch(s.release());
+        changed = true;
+    }
+
+    if s.playing && transport.position() >= s.next {
+        let i = s.index.map_or(0, |i| (i + 1) % s.steps.len());
+        s.index = Some(i);
+        s.next += s.steps[i].1;
+        presses.send_batch(s.press(base_note.0));
+        changed = true;
+    }
+
+    if changed {
+        for mut text in &mut label {
+            text.sections[0].value = s.label();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn progression_with_durations_and_tempo() {
+        let (steps, bpm) = parse_progression("C Am:2 @100", 3).unwrap();
+        assert_eq!(steps, vec![
+            (Chord {root: 3, chord_type: 0, bass: None}, DEFAULT_BEATS),
+            (Chord {root: 0, chord_type: 1, bass: None}, 2.),
+        ]);
+        assert_eq!(bpm, Some(100.));
+    }
+
+    #[test]
+    fn progression_of_roman_numerals_in_the_key() {
+        // in G, with bars
+        let (steps, bpm) = parse_progression("I | vi:2 IV:2 | V7", 10).unwrap();
+        let chords: Vec<(usize, usize)> = steps.iter().map(|(c, _)| (c.root, c.chord_type)).collect();
+        assert_eq!(chords, vec![(10, 0), (7, 1), (3, 0), (5, 2)]);
+        assert_eq!(bpm, None);
+    }
+
+    #[test]
+    fn wrong_progressions() {
+        for text in ["", "|", "C Xm", "C:0", "C:-1", "C @fast", "C @0", "é:2"] {
+            assert!(parse_progression(text, 3).is_err(), "{}", text);
+        }
+    }
+}
@@ -0,0 +1,135 @@
+use bevy::prelude::*;
+use bevy::utils::HashMap;
+
+use std::path::Path;
+
+use super::{NoteEvent, BaseNote, BASE_MIDI};
+use super::musicxml::{RecordedNote, to_musicxml};
+use super::transport::Transport;
+use super::text_input::TextEntry;
+
+const LABEL_OFFSET: Vec3 = Vec3::new(300., -250., 0.);
+
+// Records the notes played, to write them as a score
+#[derive(Resource, Default)]
+pub struct Session {
+    recording: bool,
+    // beats since the start of the first bar of the recording
+    beats: f32,
+    notes: Vec<RecordedNote>,
+    // the notes sounding, by position on the circle, with their pitch and start
+    open: HashMap<usize, (i32, f32)>,
+    message: Option<String>,
+}
+
+impl Session {
+    fn start(&mut self, transport: &Transport) {
+        self.recording = true;
+        self.notes.clear();
+        self.open.clear();
+        self.message = None;
+        // so that the bars of the score are the bars of the transport
+        self.beats = if transport.playing() {transport.position() % transport.beats_per_bar() as f32} else {0.};
+    }
+
+    fn stop(&mut self, beats_per_bar: usize) {
+        self.recording = false;
+        for (_, (pitch, start)) in self.open.drain() {
+            self.notes.push(RecordedNote {pitch, start, end: self.beats});
+        }
+
+        if self.notes.is_empty() {
+            self.message = Some("nothing was played".to_string());
+            return
+        }
+
+        // the first name not already taken
+        let path = (1..).map(|i| format!("session-{}.musicxml", i)).find(|p| !Path::new(p).exists()).unwrap();
+        let xml = to_musicxml(&self.notes, beats_per_bar, "note-circle session");
+        self.message = Some(match std::fs::write(&path, xml) {
+            Ok(()) => format!("saved to {}", path),
+            Err(e) => format!("cannot save: {}", e),
+        });
+    }
+
+    fn label(&self) -> String {
+        if self.recording {
+            return format!("session: recording, {} note(s)", self.notes.len() + self.open.len())
+        }
+        match &self.message {
+            Some(m) => format!("session: {}", m),
+            None => "session: not recording".to_string(),
+        }
+    }
+}
+
+#[derive(Component)]
+pub struct SessionLabel;
+
+pub fn create_session_label(
+    mut commands: Commands,
+    session: Res<Session>,
+) {
+    let text_style : TextStyle = TextStyle {
+        color: Color::GRAY,
+        font_size: 14.,
+        font: Default::default(),
+    };
+
+    let label = Text2dBundle {
+        text: Text::from_section(session.label(), text_style),
+        transform: Transform::from_translation(LABEL_OFFSET),
+        ..default()
+    };
+
+    commands.spawn((label, SessionLabel));
+}
+
+// Insert starts the recording, and writes it as MusicXML when pressed again
+#[allow(clippy::too_many_arguments)]
+pub fn record_session(
+    time: Res<Time>,
+    keyboard_input: Res<Input<KeyCode>>,
+    base_note: Res<BaseNote>,
+    transport: Res<Transport>,
+    entry: Res<TextEntry>,
+    mut note_events: EventReader<NoteEvent>,
+    mut session: ResMut<Session>,
+    mut label: Query<&mut Text, With<SessionLabel>>,
+) {
+    let s = &mut *session;
+
+    if keyboard_input.just_pressed(KeyCode::Insert) && !entry.active() {
+        if s.recording {
+            s.stop(transport.beats_per_bar());
+        }
+        else {
+            s.start(&transport);
+        }
+    }
+
+    if !s.recording {
+        note_events.clear();
+    }
+    else {
+        s.beats += time.delta_seconds() * transport.bpm() / 60.;
+
+        for event in note_events.read() {
+            let position = event.position.0;
+            if event.on {
+                let pitch = BASE_MIDI + (base_note.0 + position) as i32;
+                s.open.insert(position, (pitch, s.beats));
+            }
+            else if let Some((pitch, start)) = s.open.remove(&position) {
+                s.notes.push(RecordedNote {pitch, start, end: s.beats});
+            }
+        }
+    }
+
+    let value = s.label();
+    for mut text in &mut label {
+        if text.sections[0].value != value {
+            text.sections[0].value = value.clone();
+        }
+    }
+}
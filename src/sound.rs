@@ -5,10 +5,17 @@ use bevy::audio::Source;
 use bevy::utils::Duration;
 
 use std::f32::consts::PI;
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
 
-use super::{NotePosition, BaseNote};
+use super::{NotePosition, BaseNote, Playing};
 
-static SAMPLE_RATE: u32 = 44_100;
+pub static SAMPLE_RATE: u32 = 44_100;
+
+// how many of the last samples of a voice are kept for the visualisation
+pub const TAP_LENGTH: usize = 8192;
+// the audio thread only shares its samples by chunks, to lock less often
+const TAP_CHUNK: usize = 256;
 
 pub static SINE_SPECTRUM: [Sinusoid; 3] = [
     Sinusoid {amplitude: 0.25, phase: 0., frequency_multiple: 1.0},
@@ -16,6 +23,13 @@ pub static SINE_SPECTRUM: [Sinusoid; 3] = [
     Sinusoid {amplitude: 0.21, phase: 0., frequency_multiple: 1.05},
 ];
 
+// a short inharmonic sound, for the metronome
+pub static CLICK_SPECTRUM: [Sinusoid; 3] = [
+    Sinusoid {amplitude: 0.4, phase: 0., frequency_multiple: 1.0},
+    Sinusoid {amplitude: 0.2, phase: 0., frequency_multiple: 2.76},
+    Sinusoid {amplitude: 0.1, phase: 0., frequency_multiple: 5.4},
+];
+
 // pub static TRIANGLE_SPECTRUM: [Sinusoid; 5] = [
 //     Sinusoid::NULL,
 //     Sinusoid {amplitude: 0.5, phase: 0.},
@@ -52,20 +66,56 @@ pub static SINE_SPECTRUM: [Sinusoid; 3] = [
 pub fn create_samples(base_note: Res<BaseNote>,
                       mut assets: ResMut<Assets<Synth>>,
                       mut commands: Commands,
-                      query: Query<(Entity, &NotePosition)>,
+                      query: Query<(Entity, &NotePosition, &Playing)>,
                       ) {
 
-    for (e, note) in &query {
+    for (e, note, playing) in &query {
+        let tap = Tap::default();
         let sound = AudioSourceBundle {
-            source: assets.add(Synth::new(note.note(base_note.0).to_freq(), SINE_SPECTRUM.into())),
+            source: assets.add(Synth::new(note.note(base_note.0).to_freq(), SINE_SPECTRUM.into(), tap.clone())),
             settings: PlaybackSettings {
                 mode: PlaybackMode::Remove,
+                paused: !playing.0,
                 ..Default::default()
             }
         };
 
         commands.entity(e).remove::<AudioSink>();
-        commands.entity(e).insert(sound);
+        commands.entity(e).insert((sound, tap));
+    }
+}
+
+// The sinks are only made by bevy after `create_samples`, and their note can be switched
+// on or off in between, like by a song opened at startup: they are set as their note is.
+pub fn sync_new_sinks(
+    sinks: Query<(&AudioSink, &Playing), Added<AudioSink>>,
+) {
+    for (sink, playing) in &sinks {
+        if playing.0 {sink.play()} else {sink.pause()}
+    }
+}
+
+// The last samples generated by a voice.
+// It is filled by the audio thread, and read to draw the sound
+#[derive(Component, Clone, Default)]
+pub struct Tap(Arc<Mutex<VecDeque<f32>>>);
+
+impl Tap {
+    fn push(&self, samples: &[f32]) {
+        if let Ok(mut buffer) = self.0.try_lock() {
+            buffer.extend(samples);
+            let extra = buffer.len().saturating_sub(TAP_LENGTH);
+            buffer.drain(..extra);
+        }
+    }
+
+    // the last n samples, padded with zeros at the beginning if needed
+    pub fn last(&self, n: usize) -> Vec<f32> {
+        let buffer = self.0.lock().unwrap();
+        let available = buffer.len().min(n);
+        let mut samples = vec![0.; n - available];
+        samples.extend(buffer.range(buffer.len() - available..));
+        samples
     }
 }
 
@@ -85,32 +135,57 @@ impl Sinusoid {
 #[derive(Asset, TypePath)]
 pub struct Synth {
     frequency: f32,
-    spectrum: Vec<Sinusoid>
+    spectrum: Vec<Sinusoid>,
+    tap: Tap,
+    // how long the sound takes to fade out, in seconds, if it is not sustained
+    decay: Option<f32>,
 }
 
 impl Synth {
-    pub fn new(frequency: f32, spectrum: Vec<Sinusoid>) -> Self {
+    pub fn new(frequency: f32, spectrum: Vec<Sinusoid>, tap: Tap) -> Self {
         Self {
             frequency,
-            spectrum
+            spectrum,
+            tap,
+            decay: None,
         }
     }
+
+    // the sound fades out and stops after `decay` seconds
+    pub fn decaying(self, decay: f32) -> Self {
+        Self {decay: Some(decay), ..self}
+    }
 }
 
 pub struct SynthDecoder {
     // how far along one period the wave is (between 0 and 1)
     current_phase: f32,
     step: f32,
-    spectrum: Vec<Sinusoid>
+    spectrum: Vec<Sinusoid>,
+    tap: Tap,
+    // samples not yet shared with the tap
+    chunk: Vec<f32>,
+    gain: f32,
+    // by how much the gain is multiplied at each sample
+    fade: f32,
+    // how many samples are left, if the sound is not sustained
+    remaining: Option<usize>,
 }
 
 
 impl SynthDecoder {
-    fn new(frequency: f32, spectrum: Vec<Sinusoid>) -> Self {
+    fn new(frequency: f32, spectrum: Vec<Sinusoid>, tap: Tap, decay: Option<f32>) -> Self {
+        let remaining = decay.map(|d| (d * SAMPLE_RATE as f32) as usize);
         SynthDecoder {
             current_phase: 0.,
             step: 2.0 * PI * frequency / SAMPLE_RATE as f32,
             spectrum,
+            tap,
+            chunk: Vec::with_capacity(TAP_CHUNK),
+            gain: 1.,
+            // down to -60 dB at the end
+            fade: remaining.map_or(1., |n| 0.001_f32.powf(1. / n.max(1) as f32)),
+            remaining,
         }
     }
 }
@@ -119,14 +194,28 @@ impl Iterator for SynthDecoder {
     type Item = f32;
 
     fn next(&mut self) -> Option<Self::Item> {
+        if let Some(remaining) = &mut self.remaining {
+            if *remaining == 0 {
+                return None
+            }
+            *remaining -= 1;
+        }
+
         // we loop back round to 2pi to avoid floating point inaccuracies
         self.current_phase = (self.current_phase + self.step)%(2.0 * PI);
-        Some(
-        self.spectrum
+        let sample = self.spectrum
             .iter()
             .map(|coeff| coeff.generate_signal(self.current_phase))
-            .sum::<f32>()
-        )
+            .sum::<f32>() * self.gain;
+        self.gain *= self.fade;
+
+        self.chunk.push(sample);
+        if self.chunk.len() == TAP_CHUNK {
+            self.tap.push(&self.chunk);
+            self.chunk.clear();
+        }
+
+        Some(sample)
     }
 }
 // `Source` is what allows the audio source to be played by bevy.
@@ -145,7 +234,7 @@ impl Source for SynthDecoder {
     }
 
     fn total_duration(&self) -> Option<Duration> {
-        None
+        self.remaining.map(|n| Duration::from_secs_f32(n as f32 / SAMPLE_RATE as f32))
     }
 }
 
@@ -155,6 +244,6 @@ impl Decodable for Synth {
     type Decoder = SynthDecoder;
 
     fn decoder(&self) -> Self::Decoder {
-        SynthDecoder::new(self.frequency, self.spectrum.clone())
+        SynthDecoder::new(self.frequency, self.spectrum.clone(), self.tap.clone(), self.decay)
     }
 }
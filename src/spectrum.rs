@@ -0,0 +1,82 @@
+use std::f32::consts::PI;
+
+// Magnitude of the discrete Fourier transform of the signal,
+// for the frequencies between 0 and half the sample rate.
+// The signal is windowed (Hann) and its length must be a power of 2
+pub fn spectrum(signal: &[f32]) -> Vec<f32> {
+    let n = signal.len();
+    assert!(n.is_power_of_two(), "the length of the signal must be a power of 2");
+
+    let mut re: Vec<f32> = signal.iter()
+        .enumerate()
+        .map(|(i, x)| x * 0.5 * (1. - f32::cos(2. * PI * i as f32 / n as f32)))
+        .collect();
+    let mut im = vec![0.; n];
+
+    fft(&mut re, &mut im);
+
+    re.iter().zip(&im)
+        .take(n / 2)
+        .map(|(a, b)| (a * a + b * b).sqrt() / n as f32)
+        .collect()
+}
+
+// in-place radix-2 Cooley-Tukey
+fn fft(re: &mut [f32], im: &mut [f32]) {
+    let n = re.len();
+
+    // bit reversal permutation
+    let mut j = 0;
+    for i in 1..n {
+        let mut bit = n >> 1;
+        while j & bit != 0 {
+            j ^= bit;
+            bit >>= 1;
+        }
+        j |= bit;
+        if i < j {
+            re.swap(i, j);
+            im.swap(i, j);
+        }
+    }
+
+    let mut len = 2;
+    while len <= n {
+        let angle = -2. * PI / len as f32;
+        for start in (0..n).step_by(len) {
+            for k in 0..len / 2 {
+                let (w_im, w_re) = (angle * k as f32).sin_cos();
+                let a = start + k;
+                let b = a + len / 2;
+                let t_re = re[b] * w_re - im[b] * w_im;
+                let t_im = re[b] * w_im + im[b] * w_re;
+                re[b] = re[a] - t_re;
+                im[b] = im[a] - t_im;
+                re[a] += t_re;
+                im[a] += t_im;
+            }
+        }
+        len <<= 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sine_lands_in_its_bin() {
+        let n = 256;
+        for bin in [1, 10, 100] {
+            let signal: Vec<f32> = (0..n).map(|i| (2. * PI * bin as f32 * i as f32 / n as f32).sin()).collect();
+            let s = spectrum(&signal);
+            assert_eq!(s.len(), n / 2);
+
+            let peak = (0..s.len()).max_by(|&a, &b| s[a].total_cmp(&s[b])).unwrap();
+            assert_eq!(peak, bin);
+            // the amplitude is halved by the window and shared with the negative frequency
+            assert!((s[bin] - 0.25).abs() < 1e-3);
+            assert!(s.iter().enumerate().all(|(i, m)| i.abs_diff(bin) <= 1 || *m < 1e-3));
+        }
+    }
+}
@@ -0,0 +1,279 @@
+use bevy::prelude::*;
+
+use super::{NotePosition, Playing, BaseNote, HideNotes, BASE_MIDI};
+
+// middle C is at the center
+const OFFSET: Vec2 = Vec2::new(300., 0.);
+const WIDTH: f32 = 460.;
+// vertical distance between a line and the next space
+const STEP: f32 = 6.;
+
+// diatonic steps from C0: the lines of the treble and bass staves
+const MIDDLE_C: i32 = 28;
+const TREBLE_LINES: [i32; 5] = [30, 32, 34, 36, 38];
+const BASS_LINES: [i32; 5] = [18, 20, 22, 24, 26];
+
+// horizontal positions, from the left of the staff
+const KEY_SIGNATURE_X: f32 = 40.;
+const HISTORY_X: f32 = 150.;
+const CHORD_SPACING: f32 = 70.;
+const CURRENT_X: f32 = 420.;
+const N_HISTORY: usize = 4;
+
+// half tones from C of each natural note, C D E F G A B
+static NATURALS: [i32; 7] = [0, 2, 4, 5, 7, 9, 11];
+static MAJOR_SCALE: [i32; 7] = [0, 2, 4, 5, 7, 9, 11];
+
+// where the accidentals of a key signature are drawn on the treble staff,
+// in the order they appear: F C G D A E B for sharps, B E A D G C F for flats
+static SHARPS_STEPS: [i32; 7] = [38, 35, 39, 36, 33, 37, 34];
+static FLATS_STEPS: [i32; 7] = [34, 37, 33, 36, 32, 35, 31];
+
+// A note written on the staff.
+// `letter` is 0 for C, 1 for D, ...
+// `accidental` is the number of sharps (negative for flats)
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Spelling {
+    pub letter: i32,
+    pub accidental: i32,
+}
+
+// a major key, spelled with sharps or with flats
+pub struct Key {
+    tonic: Spelling,
+    // number of sharps in the key signature, negative for flats
+    pub signature: i32,
+}
+
+impl Key {
+    pub fn major(tonic_pitch_class: i32) -> Self {
+        let fifths = (tonic_pitch_class * 7).rem_euclid(12);
+        let signature = if fifths <= 6 {fifths} else {fifths - 12};
+
+        // the natural note just below or just above
+        let tonic = if signature >= 0 {
+            let letter = NATURALS.iter().rposition(|&n| n <= tonic_pitch_class).unwrap() as i32;
+            Spelling {letter, accidental: tonic_pitch_class - NATURALS[letter as usize]}
+        }
+        else {
+            let letter = NATURALS.iter().position(|&n| n >= tonic_pitch_class).unwrap() as i32;
+            Spelling {letter, accidental: tonic_pitch_class - NATURALS[letter as usize]}
+        };
+
+        Key {tonic, signature}
+    }
+
+    fn scale(&self) -> [(i32, Spelling); 7] {
+        let tonic = NATURALS[self.tonic.letter as usize] + self.tonic.accidental;
+        std::array::from_fn(|i| {
+            let letter = (self.tonic.letter + i as i32) % 7;
+            let pitch_class = (tonic + MAJOR_SCALE[i]).rem_euclid(12);
+            let accidental = (pitch_class - NATURALS[letter as usize] + 6).rem_euclid(12) - 6;
+            (pitch_class, Spelling {letter, accidental})
+        })
+    }
+
+    // the notes out of the scale are spelled as an altered note of the scale,
+    // with sharps in a sharp key and with flats in a flat key
+    pub fn spell(&self, pitch_class: i32) -> Spelling {
+        let scale = self.scale();
+        let find = |pc: i32| scale.iter().find(|(p, _)| *p == pc.rem_euclid(12)).map(|(_, s)| *s);
+
+        if let Some(s) = find(pitch_class) {
+            return s
+        }
+
+        let raised = find(pitch_class - 1).map(|s| Spelling {accidental: s.accidental + 1, ..s});
+        let lowered = find(pitch_class + 1).map(|s| Spelling {accidental: s.accidental - 1, ..s});
+
+        if self.signature >= 0 {raised.or(lowered).unwrap()} else {lowered.or(raised).unwrap()}
+    }
+
+    fn signature_accidental(&self, letter: i32) -> i32 {
+        self.scale().iter().find(|(_, s)| s.letter == letter).unwrap().1.accidental
+    }
+}
+
+// where a midi note is written on the staff, and with which accidental
+pub fn staff_note(key: &Key, midi: i32) -> (i32, Spelling) {
+    let spelling = key.spell(midi.rem_euclid(12));
+    let octave = (midi - spelling.accidental).div_euclid(12) - 1;
+    (7 * octave + spelling.letter, spelling)
+}
+
+#[derive(Resource, Default)]
+pub struct StaffHistory {
+    // the biggest chord held since all the notes were released
+    current: Vec<usize>,
+    chords: Vec<Vec<usize>>,
+    show: bool,
+}
+
+#[derive(Component)]
+pub struct StaffText;
+
+pub fn create_staff(
+    mut commands: Commands,
+) {
+    let text_style : TextStyle = TextStyle {
+        color: Color::WHITE,
+        font_size: 24.,
+        font: Default::default(),
+    };
+
+    let left = OFFSET.x - WIDTH / 2. + 12.;
+    for (clef, step) in [("G", TREBLE_LINES[1]), ("F", BASS_LINES[3])] {
+        let text = Text2dBundle {
+            text: Text::from_section(clef, text_style.clone()),
+            transform: Transform::from_translation(Vec3::new(left, y(step), 0.)),
+            ..default()
+        };
+        commands.spawn((text, StaffText));
+    }
+}
+
+pub fn clear_staff(
+    mut commands: Commands,
+    texts: Query<Entity, With<StaffText>>,
+) {
+    for e in &texts {
+        commands.entity(e).despawn();
+    }
+}
+
+pub fn record_staff_history(
+    keyboard_input: Res<Input<KeyCode>>,
+    hide: Res<HideNotes>,
+    notes: Query<(&NotePosition, &Playing)>,
+    mut history: ResMut<StaffHistory>,
+) {
+    if keyboard_input.just_pressed(KeyCode::F2) {
+        history.show = !history.show;
+    }
+
+    // the hidden notes are not written
+    if hide.0 {
+        return
+    }
+
+    let mut playing: Vec<usize> = notes.iter().filter(|(_, p)| p.0).map(|(x, _)| x.0).collect();
+    playing.sort();
+
+    if playing.is_empty() && !history.current.is_empty() {
+        let chord = std::mem::take(&mut history.current);
+        history.chords.push(chord);
+        if history.chords.len() > N_HISTORY {
+            history.chords.remove(0);
+        }
+    }
+    else if playing.len() > history.current.len() {
+        history.current = playing;
+    }
+}
+
+fn y(step: i32) -> f32 {
+    OFFSET.y + (step - MIDDLE_C) as f32 * STEP
+}
+
+fn draw_accidental(gizmos: &mut Gizmos, center: Vec2, accidental: i32) {
+    let line = |gizmos: &mut Gizmos, a: Vec2, b: Vec2| gizmos.line_2d(center + a, center + b, Color::WHITE);
+
+    match accidental {
+        0 => {
+            line(gizmos, Vec2::new(-3., -3.), Vec2::new(-3., 9.));
+            line(gizmos, Vec2::new(3., -9.), Vec2::new(3., 3.));
+            line(gizmos, Vec2::new(-3., 2.), Vec2::new(3., 3.));
+            line(gizmos, Vec2::new(-3., -3.), Vec2::new(3., -2.));
+        }
+        1 => {
+            line(gizmos, Vec2::new(-2., -8.), Vec2::new(-2., 8.));
+            line(gizmos, Vec2::new(2., -8.), Vec2::new(2., 8.));
+            line(gizmos, Vec2::new(-5., -4.), Vec2::new(5., -2.));
+            line(gizmos, Vec2::new(-5., 2.), Vec2::new(5., 4.));
+        }
+        2 => {
+            line(gizmos, Vec2::new(-3., -3.), Vec2::new(3., 3.));
+            line(gizmos, Vec2::new(-3., 3.), Vec2::new(3., -3.));
+        }
+        -1 => {
+            line(gizmos, Vec2::new(-3., -3.), Vec2::new(-3., 11.));
+            gizmos.circle_2d(center + Vec2::new(0., -1.), 3., Color::WHITE);
+        }
+        -2 => {
+            draw_accidental(gizmos, center - Vec2::new(4., 0.), -1);
+            draw_accidental(gizmos, center + Vec2::new(3., 0.), -1);
+        }
+        _ => {}
+    }
+}
+
+fn draw_chord(gizmos: &mut Gizmos, key: &Key, x: f32, midi_notes: &[i32], color: Color) {
+    let mut notes: Vec<(i32, Spelling)> = midi_notes.iter().map(|&m| staff_note(key, m)).collect();
+    notes.sort_by_key(|(step, _)| *step);
+
+    for (i, &(step, spelling)) in notes.iter().enumerate() {
+        // a second is written on the other side of the stem
+        let shifted = i > 0 && notes[i-1].0 == step - 1 && (i < 2 || notes[i-2].0 != step - 2);
+        let center = Vec2::new(x + if shifted {10.} else {0.}, y(step));
+
+        for r in [5., 3.5, 2., 0.5] {
+            gizmos.circle_2d(center, r, color);
+        }
+
+        // ledger lines
+        let ledgers = (TREBLE_LINES[4] + 2..=step).step_by(2)
+            .chain((step..BASS_LINES[0]).filter(|s| (BASS_LINES[0] - s) % 2 == 0))
+            .chain((step == MIDDLE_C).then_some(MIDDLE_C));
+        for s in ledgers {
+            gizmos.line_2d(Vec2::new(center.x - 9., y(s)), Vec2::new(center.x + 9., y(s)), Color::WHITE);
+        }
+
+        if spelling.accidental != key.signature_accidental(spelling.letter) {
+            draw_accidental(gizmos, Vec2::new(x - 14. - 8. * (i % 2) as f32, y(step)), spelling.accidental);
+        }
+    }
+}
+
+pub fn draw_staff(
+    mut gizmos: Gizmos,
+    base_note: Res<BaseNote>,
+    history: Res<StaffHistory>,
+    hide: Res<HideNotes>,
+    notes: Query<(&NotePosition, &Playing)>,
+) {
+    let left = OFFSET.x - WIDTH / 2.;
+    let right = OFFSET.x + WIDTH / 2.;
+
+    for step in TREBLE_LINES.iter().chain(&BASS_LINES) {
+        gizmos.line_2d(Vec2::new(left, y(*step)), Vec2::new(right, y(*step)), Color::WHITE);
+    }
+    gizmos.line_2d(Vec2::new(left, y(BASS_LINES[0])), Vec2::new(left, y(TREBLE_LINES[4])), Color::WHITE);
+
+    let base_midi = BASE_MIDI + base_note.0 as i32;
+    let key = Key::major(base_midi.rem_euclid(12));
+
+    let n = key.signature.unsigned_abs() as usize;
+    let steps = if key.signature >= 0 {SHARPS_STEPS} else {FLATS_STEPS};
+    for (i, step) in steps.iter().take(n).enumerate() {
+        let x = left + KEY_SIGNATURE_X + 9. * i as f32;
+        // the same accidental, on the bass staff
+        for step in [*step, step - 14] {
+            draw_accidental(&mut gizmos, Vec2::new(x, y(step)), key.signature.signum());
+        }
+    }
+
+    if history.show {
+        let start = N_HISTORY - history.chords.len();
+        for (i, chord) in history.chords.iter().enumerate() {
+            let x = left + HISTORY_X + CHORD_SPACING * (start + i) as f32;
+            let midi_notes: Vec<i32> = chord.iter().map(|&p| base_midi + p as i32).collect();
+            draw_chord(&mut gizmos, &key, x, &midi_notes, Color::GRAY);
+        }
+    }
+
+    let playing: Vec<i32> = notes.iter()
+        .filter(|(_, p)| p.0 && !hide.0)
+        .map(|(x, _)| base_midi + x.0 as i32)
+        .collect();
+    draw_chord(&mut gizmos, &key, left + CURRENT_X, &playing, Color::WHITE);
+}
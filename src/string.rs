@@ -1,4 +1,4 @@
-use super::{Note, STRING_LENGTH};
+use super::{Note, STRING_LENGTH, N};
 use bevy::render::color::Color;
 use bevy::ecs::component::Component;
 use bevy::gizmos::gizmos::Gizmos;
@@ -25,41 +25,79 @@ impl StringState {
     }
 
     pub fn step(&mut self, p: &StringParams) {
-        let acceleration = compute_acceleration(p, &self);
+        let acceleration = compute_acceleration(p, self);
 
-        if self.current.len() == 0 {
+        if self.current.is_empty() {
             return 
         }
 
         self.time += p.dt;
 
-        for i in 0..p.n_samples {
+        for (i, a) in acceleration.iter().enumerate() {
             // attention: on inverse last et current dans
-            self.last[i] = 2.*self.current[i]-self.last[i] + p.dt*p.dt*acceleration[i]
+            self.last[i] = 2.*self.current[i]-self.last[i] + p.dt*p.dt*a
         }
 
-        self.last[0] = compute_excitation(p, &self);
+        self.last[0] = compute_excitation(p, self);
         
         std::mem::swap(&mut self.last, &mut self.current);
     }
 
-    pub fn draw(&self, p: &StringParams, mut gizmos: Gizmos) {
+    // kinetic and potential energy of the string, for a unit linear density
+    pub fn energy(&self, p: &StringParams) -> (f32, f32) {
         let n = self.current.len();
+        let dx = p.length / p.n_samples as f32;
+
+        let mut kinetic = 0.;
+        let mut potential = 0.;
+
         for i in 0..n {
-            let p = OFFSET + Vec2::new(
-                -STRING_LENGTH/2. + i as f32 / n as f32 * p.length,
-                self.current[i]*STRING_LENGTH/3.
-            );
-            gizmos.circle_2d(p, 1., Color::BLUE);
+            let v = (self.current[i] - self.last[i]) / p.dt;
+            kinetic += 0.5 * v * v * dx;
+
+            if i + 1 < n {
+                let slope = (self.current[i+1] - self.current[i]) / dx;
+                potential += 0.5 * p.c * p.c * slope * slope * dx;
+            }
+
+            let weight = if i==0 || i==n-1 {1.} else {p.junction_weight(i)};
+            potential += 0.5 * weight * p.spring_coeff * self.current[i] * self.current[i] * dx;
         }
+
+        (kinetic, potential)
+    }
+
+    // displacement at a given fraction of the string
+    pub fn displacement(&self, fraction: f32) -> f32 {
+        let n = self.current.len();
+        if n == 0 {
+            return 0.
+        }
+        self.current[((fraction * n as f32) as usize).min(n-1)]
+    }
+
+    pub fn draw(&self, p: &StringParams, mut gizmos: Gizmos) {
+        let origin = OFFSET - Vec2::new(STRING_LENGTH/2., 0.);
+        self.draw_along(p, origin, Vec2::X, 1., &mut gizmos);
+
         for note in p.chord.iter() {
-            let p = OFFSET + Vec2::new(
-                -STRING_LENGTH/2. + note.relative_length() * STRING_LENGTH,
-                0.
-            );
+            let p = origin + Vec2::new(note.relative_length() * STRING_LENGTH, 0.);
             gizmos.circle_2d(p, 5., note.color());
         }
     }
+
+    // draw the string starting from `origin` in the given direction,
+    // the displacement being perpendicular to it
+    pub fn draw_along(&self, p: &StringParams, origin: Vec2, direction: Vec2, scale: f32, gizmos: &mut Gizmos) {
+        let n = self.current.len();
+        for i in 0..n {
+            let p = origin + scale * (
+                direction * (i as f32 / n as f32 * p.length)
+                + direction.perp() * self.current[i]*STRING_LENGTH/3.
+            );
+            gizmos.circle_2d(p, 1., Color::BLUE);
+        }
+    }
 }
 
 #[derive(Clone, Debug, Component)]
@@ -76,10 +114,38 @@ pub struct StringParams {
     pub excitation_coeff: f32,
 }
 
+impl Default for StringParams {
+    fn default() -> Self {
+        StringParams {
+            length: STRING_LENGTH,
+            n_samples: N,
+            dt: 0.02,
+            c: 100.,
+            chord: vec![],
+            spring_coeff: 10.00,
+            solid_friction_coeff: 50.,
+            liquid_friction_coeff: 0.015,
+            steps_per_render: 10,
+            excitation_coeff: 0.05,
+        }
+    }
+}
+
 impl StringParams {
-    fn is_junction(&self, i: usize) -> bool {
+    // index of the sample where the note is pinned.
+    // It is usually not an integer, so it falls between two samples
+    fn junction_index(&self, note: Note) -> f32 {
+        let dx = self.length / self.n_samples as f32;
+        note.relative_length() * STRING_LENGTH / dx
+    }
+
+    // how much the sample i is held by the junctions, between 0 and 1.
+    // A junction between two samples is shared linearly between them
+    fn junction_weight(&self, i: usize) -> f32 {
         self.chord.iter()
-            .any(|note| (note.relative_length() * 150.) as usize == i)
+            .map(|&note| (1. - (self.junction_index(note) - i as f32).abs()).max(0.))
+            .sum::<f32>()
+            .min(1.)
     }
 }
 
@@ -126,16 +192,30 @@ fn compute_acceleration(p: &StringParams, state: &StringState) -> Vec<f32> {
         (safe_index(i as i32+1)+safe_index(i as i32-1)-2.*safe_index(i as i32))/(dx*dx)
     ;
 
-    for i in 0..p.n_samples {
-        acc[i] = p.c*p.c*laplacian(i) - 
+    for (i, a) in acc.iter_mut().enumerate() {
+        *a = p.c*p.c*laplacian(i) - 
             p.liquid_friction_coeff*time_derivative(i)*time_derivative(i).abs();
 
-        if p.is_junction(i) || i==0 || i==p.n_samples-1{
-            acc[i] += - p.spring_coeff * state.current[i]
-                      - p.solid_friction_coeff * time_derivative(i)
-            ;
-        }
+        let weight = if i==0 || i==p.n_samples-1 {1.} else {p.junction_weight(i)};
+
+        *a += - weight * p.spring_coeff * state.current[i]
+                  - weight * p.solid_friction_coeff * time_derivative(i)
+        ;
     }
 
     acc
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn held_octave_pins_the_middle() {
+        let p = StringParams {chord: vec![Note(0.), Note(1.)], ..Default::default()};
+        let middle = p.n_samples / 2;
+
+        assert!((p.junction_index(Note(1.)) - middle as f32).abs() < 1e-3);
+        assert!((p.junction_weight(middle) - 1.).abs() < 1e-3);
+    }
+}
@@ -0,0 +1,122 @@
+use bevy::prelude::*;
+
+// above the on-screen piano
+const OFFSET: Vec3 = Vec3::new(0., -270., 0.);
+
+// what the text being typed is for
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TextTarget {
+    Progression,
+    Chord,
+}
+
+impl TextTarget {
+    fn prompt(self) -> &'static str {
+        match self {
+            TextTarget::Progression => "progression (like \"C Am:2 F:2 G @100\" or \"I vi IV V\")",
+            TextTarget::Chord => "chord (like \"Cmaj7/G\" or \"F#m7b5\")",
+        }
+    }
+}
+
+// While some text is typed, the keys do not play notes
+#[derive(Resource, Default)]
+pub struct TextEntry {
+    target: Option<TextTarget>,
+    buffer: String,
+}
+
+impl TextEntry {
+    pub fn active(&self) -> bool {
+        self.target.is_some()
+    }
+
+    pub fn open(&mut self, target: TextTarget) {
+        self.target = Some(target);
+        self.buffer.clear();
+    }
+
+    // the text typed so far, if it is for `target`
+    pub fn editing(&self, target: TextTarget) -> Option<&str> {
+        (self.target == Some(target)).then_some(self.buffer.as_str())
+    }
+}
+
+// sent when the text is validated with the return key
+#[derive(Event)]
+pub struct TextSubmitted {
+    pub target: TextTarget,
+    pub text: String,
+}
+
+#[derive(Component)]
+pub struct TextEntryLabel;
+
+pub fn create_text_entry_label(
+    mut commands: Commands,
+) {
+    let text_style : TextStyle = TextStyle {
+        color: Color::WHITE,
+        font_size: 16.,
+        font: Default::default(),
+    };
+
+    let label = Text2dBundle {
+        text: Text::from_sections([
+            TextSection::new("", text_style.clone()),
+            TextSection::new("", TextStyle {color: Color::YELLOW, ..text_style}),
+        ]),
+        transform: Transform::from_translation(OFFSET),
+        ..default()
+    };
+
+    commands.spawn((label, TextEntryLabel));
+}
+
+pub fn text_entry_system(
+    mut characters: EventReader<ReceivedCharacter>,
+    keyboard_input: Res<Input<KeyCode>>,
+    mut entry: ResMut<TextEntry>,
+    mut submitted: EventWriter<TextSubmitted>,
+    mut label: Query<&mut Text, With<TextEntryLabel>>,
+) {
+    let target = match entry.target {
+        Some(t) => t,
+        None => {
+            characters.clear();
+            return
+        }
+    };
+
+    for c in characters.read() {
+        if !c.char.is_control() {
+            entry.buffer.push(c.char);
+        }
+    }
+
+    if keyboard_input.just_pressed(KeyCode::Back) {
+        entry.buffer.pop();
+    }
+
+    if keyboard_input.just_pressed(KeyCode::Return) {
+        submitted.send(TextSubmitted {target, text: std::mem::take(&mut entry.buffer)});
+        entry.target = None;
+    }
+
+    if keyboard_input.just_pressed(KeyCode::Escape) {
+        entry.target = None;
+    }
+
+    for mut text in &mut label {
+        match entry.target {
+            Some(t) => {
+                text.sections[0].value = format!("{}: ", t.prompt());
+                text.sections[1].value = format!("{}_", entry.buffer);
+            }
+            None => {
+                text.sections[0].value.clear();
+                text.sections[1].value.clear();
+            }
+        }
+    }
+}
@@ -0,0 +1,288 @@
+use bevy::prelude::*;
+
+use super::{NotePosition, Playing, BaseNote, HideNotes, NOTE_NAMES};
+
+const OFFSET: Vec2 = Vec2::new(300., 0.);
+const SPACING: f32 = 60.;
+// only the nodes inside this rectangle are drawn
+const HALF_SIZE: Vec2 = Vec2::new(250., 190.);
+const NODE_RADIUS: f32 = 14.;
+
+const FIFTH: usize = 7;
+const MAJOR_THIRD: usize = 4;
+
+// a major or minor triad, the root being in half tones from the base note
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Triad {
+    root: usize,
+    major: bool,
+}
+
+impl Triad {
+    fn pitch_classes(self) -> [usize; 3] {
+        let third = if self.major {4} else {3};
+        [self.root, (self.root + third) % 12, (self.root + 7) % 12]
+    }
+
+    // the triad made exactly of these pitch classes, if any
+    fn from_pitch_classes(pitch_classes: &[usize]) -> Option<Self> {
+        if pitch_classes.len() != 3 {
+            return None
+        }
+        (0..12)
+            .flat_map(|root| [Triad {root, major: true}, Triad {root, major: false}])
+            .find(|t| t.pitch_classes().iter().all(|p| pitch_classes.contains(p)))
+    }
+
+    fn name(self, base_note: usize) -> String {
+        let root = NOTE_NAMES[(self.root + base_note) % 12];
+        if self.major {root.to_string()} else {format!("{}m", root)}
+    }
+
+    // the neo-Riemannian transformations
+    fn transform(self, t: char) -> Self {
+        let (offset, major) = match (t, self.major) {
+            ('P', m) => (0, !m),
+            ('R', true) => (9, false),
+            ('R', false) => (3, true),
+            ('L', true) => (4, false),
+            ('L', false) => (8, true),
+            _ => unreachable!(),
+        };
+        Triad {root: (self.root + offset) % 12, major}
+    }
+}
+
+// the shortest sequence of P, L and R going from one triad to the other
+fn transformation(from: Triad, to: Triad) -> Option<String> {
+    let mut words = vec![String::new()];
+    for _ in 0..3 {
+        let mut next = Vec::new();
+        for w in words {
+            for t in ['P', 'L', 'R'] {
+                let word = format!("{}{}", w, t);
+                if word.chars().fold(from, Triad::transform) == to {
+                    return Some(word)
+                }
+                next.push(word);
+            }
+        }
+        words = next;
+    }
+    None
+}
+
+#[derive(Resource, Default)]
+pub struct TonnetzHistory {
+    current: Option<Triad>,
+    previous: Option<Triad>,
+}
+
+// everything spawned for the view
+#[derive(Component)]
+pub struct Tonnetz;
+
+#[derive(Component)]
+pub struct TonnetzNode {
+    pitch_class: usize,
+    position: Vec2,
+}
+
+#[derive(Component)]
+pub struct TonnetzLabel;
+
+// fifths go to the right, major thirds go up and to the right
+fn lattice_position(i: i32, j: i32) -> Vec2 {
+    SPACING * Vec2::new(i as f32 + 0.5 * j as f32, 0.75f32.sqrt() * j as f32)
+}
+
+fn lattice_pitch_class(i: i32, j: i32) -> usize {
+    (i * FIFTH as i32 + j * MAJOR_THIRD as i32).rem_euclid(12) as usize
+}
+
+fn visible(position: Vec2) -> bool {
+    position.x.abs() <= HALF_SIZE.x && position.y.abs() <= HALF_SIZE.y
+}
+
+pub fn create_tonnetz(
+    mut commands: Commands,
+    base_note: Res<BaseNote>,
+) {
+    let text_style : TextStyle = TextStyle {
+        color: Color::WHITE,
+        font_size: 14.,
+        font: Default::default(),
+    };
+
+    for i in -8..=8 {
+        for j in -4..=4 {
+            let position = lattice_position(i, j);
+            if !visible(position) {
+                continue
+            }
+            let pitch_class = lattice_pitch_class(i, j);
+
+            let name = Text2dBundle {
+                text: Text::from_section(NOTE_NAMES[(pitch_class + base_note.0) % 12], text_style.clone()),
+                transform: Transform::from_translation((OFFSET + position).extend(0.)),
+                ..default()
+            };
+            commands.spawn((name, TonnetzNode {pitch_class, position}, Tonnetz));
+        }
+    }
+
+    let label = Text2dBundle {
+        text: Text::from_section("", text_style),
+        transform: Transform::from_translation((OFFSET + Vec2::new(0., HALF_SIZE.y + 25.)).extend(0.)),
+        ..default()
+    };
+    commands.spawn((label, TonnetzLabel, Tonnetz));
+}
+
+pub fn clear_tonnetz(
+    mut commands: Commands,
+    entities: Query<Entity, With<Tonnetz>>,
+) {
+    for e in &entities {
+        commands.entity(e).despawn();
+    }
+}
+
+pub fn update_tonnetz(
+    base_note: Res<BaseNote>,
+    hide: Res<HideNotes>,
+    notes: Query<(&NotePosition, &Playing)>,
+    mut history: ResMut<TonnetzHistory>,
+    mut names: Query<(&TonnetzNode, &mut Text), Without<TonnetzLabel>>,
+    mut label: Query<&mut Text, With<TonnetzLabel>>,
+) {
+    if base_note.is_changed() {
+        for (node, mut text) in &mut names {
+            text.sections[0].value = NOTE_NAMES[(node.pitch_class + base_note.0) % 12].to_string();
+        }
+    }
+
+    // the hidden notes are not followed
+    if hide.0 {
+        return
+    }
+
+    let mut pitch_classes: Vec<usize> = notes.iter()
+        .filter(|(_, p)| p.0)
+        .map(|(x, _)| x.oclock())
+        .collect();
+    pitch_classes.sort();
+    pitch_classes.dedup();
+
+    let triad = match Triad::from_pitch_classes(&pitch_classes) {
+        Some(t) if Some(t) != history.current => t,
+        _ => return
+    };
+
+    history.previous = history.current;
+    history.current = Some(triad);
+
+    if let Ok(mut label) = label.get_single_mut() {
+        label.sections[0].value = match history.previous {
+            Some(previous) => format!(
+                "{} -> {} : {}",
+                previous.name(base_note.0),
+                triad.name(base_note.0),
+                transformation(previous, triad).unwrap_or_else(|| "?".to_string()),
+            ),
+            None => triad.name(base_note.0),
+        };
+    }
+}
+
+pub fn draw_tonnetz(
+    mut gizmos: Gizmos,
+    history: Res<TonnetzHistory>,
+    hide: Res<HideNotes>,
+    notes: Query<(&NotePosition, &Playing)>,
+    nodes: Query<&TonnetzNode>,
+) {
+    let playing: Vec<&NotePosition> = notes.iter().filter(|(_, p)| p.0 && !hide.0).map(|(x, _)| x).collect();
+
+    let mut triangles: Vec<(Triad, [Vec2; 3])> = Vec::new();
+
+    for i in -8..=8 {
+        for j in -4..=4 {
+            let a = lattice_position(i, j);
+            let b = lattice_position(i+1, j);
+            let c = lattice_position(i, j+1);
+            let d = lattice_position(i+1, j+1);
+
+            // pointing up: major triad on (i, j),
+            // pointing down: minor triad on (i, j+1)
+            let up = [a, b, c];
+            let down = [b, d, c];
+            let root = lattice_pitch_class(i, j);
+
+            for (corners, triad) in [
+                (up, Triad {root, major: true}),
+                (down, Triad {root: (root + MAJOR_THIRD) % 12, major: false}),
+            ] {
+                if corners.iter().all(|&p| visible(p)) {
+                    triangles.push((triad, corners));
+                }
+            }
+        }
+    }
+
+    let centroid = |c: &[Vec2; 3]| (c[0] + c[1] + c[2]) / 3.;
+
+    for (triad, corners) in &triangles {
+        let sounding = triad.pitch_classes().iter()
+            .all(|&p| playing.iter().any(|x| x.oclock() == p));
+
+        let color = if sounding {
+            if triad.major {Color::GOLD} else {Color::TEAL}
+        }
+        else if Some(*triad) == history.previous {
+            Color::DARK_GRAY
+        }
+        else {
+            Color::rgb(0.15, 0.15, 0.15)
+        };
+
+        gizmos.linestrip_2d(
+            [corners[0], corners[1], corners[2], corners[0]].map(|p| OFFSET + p),
+            color,
+        );
+
+        if sounding {
+            // shrink the triangle to make it look filled
+            let center = centroid(corners);
+            for k in 1..4 {
+                let s = k as f32 / 4.;
+                gizmos.linestrip_2d(
+                    [corners[0], corners[1], corners[2], corners[0]].map(|p| OFFSET + center + s * (p - center)),
+                    color,
+                );
+            }
+        }
+    }
+
+    // link the closest occurrences of the last two triads
+    if let (Some(previous), Some(current)) = (history.previous, history.current) {
+        let centers = |t: Triad| triangles.iter()
+            .filter(move |(x, _)| *x == t)
+            .map(|(_, c)| centroid(c));
+
+        let closest = centers(previous)
+            .flat_map(|a| centers(current).map(move |b| (a, b)))
+            .min_by(|x, y| x.0.distance(x.1).total_cmp(&y.0.distance(y.1)));
+
+        if let Some((a, b)) = closest {
+            gizmos.line_2d(OFFSET + a, OFFSET + b, Color::WHITE);
+            gizmos.circle_2d(OFFSET + b, 4., Color::WHITE);
+        }
+    }
+
+    for node in &nodes {
+        if let Some(x) = playing.iter().find(|x| x.oclock() == node.pitch_class) {
+            gizmos.circle_2d(OFFSET + node.position, NODE_RADIUS, x.note(0).color());
+        }
+    }
+}
@@ -0,0 +1,186 @@
+use bevy::prelude::*;
+use bevy::audio::PlaybackMode;
+
+use super::sound::{Synth, Tap, CLICK_SPECTRUM};
+use super::text_input::TextEntry;
+
+const LABEL_OFFSET: Vec3 = Vec3::new(-300., 310., 0.);
+
+const MIN_BPM: f32 = 40.;
+const MAX_BPM: f32 = 240.;
+const MAX_BEATS_PER_BAR: usize = 7;
+
+// the first beat of a bar is higher
+const CLICK_FREQUENCY: f32 = 1320.;
+const ACCENT_FREQUENCY: f32 = 1760.;
+const CLICK_DECAY: f32 = 0.05;
+
+// The tempo and the position in the music, shared by everything that plays in time
+#[derive(Resource)]
+pub struct Transport {
+    bpm: f32,
+    // the time signature is `beats_per_bar`/4
+    beats_per_bar: usize,
+    playing: bool,
+    click: bool,
+    // beats since the transport was started
+    position: f32,
+    last_beat: Option<usize>,
+}
+
+impl Default for Transport {
+    fn default() -> Self {
+        Transport {
+            bpm: 120.,
+            beats_per_bar: 4,
+            playing: true,
+            click: false,
+            position: 0.,
+            last_beat: None,
+        }
+    }
+}
+
+impl Transport {
+    pub fn bpm(&self) -> f32 {
+        self.bpm
+    }
+
+    pub fn set_bpm(&mut self, bpm: f32) {
+        self.bpm = bpm.clamp(MIN_BPM, MAX_BPM);
+    }
+
+    pub fn beats_per_bar(&self) -> usize {
+        self.beats_per_bar
+    }
+
+    pub fn playing(&self) -> bool {
+        self.playing
+    }
+
+    pub fn position(&self) -> f32 {
+        self.position
+    }
+
+    pub fn start(&mut self) {
+        self.playing = true;
+        self.position = 0.;
+        self.last_beat = None;
+    }
+
+    pub fn stop(&mut self) {
+        self.playing = false;
+    }
+
+    fn label(&self) -> String {
+        let click = if self.click {", click"} else {""};
+        if !self.playing {
+            return format!("tempo: {} bpm, {}/4, stopped{}", self.bpm, self.beats_per_bar, click)
+        }
+        let beat = self.position as usize;
+        format!(
+            "tempo: {} bpm, {}/4, bar {} beat {}{}",
+            self.bpm, self.beats_per_bar, beat / self.beats_per_bar + 1, beat % self.beats_per_bar + 1, click,
+        )
+    }
+}
+
+#[derive(Component)]
+pub struct TransportLabel;
+
+pub fn create_transport_label(
+    mut commands: Commands,
+    transport: Res<Transport>,
+) {
+    let text_style : TextStyle = TextStyle {
+        color: Color::GRAY,
+        font_size: 14.,
+        font: Default::default(),
+    };
+
+    let label = Text2dBundle {
+        text: Text::from_section(transport.label(), text_style),
+        transform: Transform::from_translation(LABEL_OFFSET),
+        ..default()
+    };
+
+    commands.spawn((label, TransportLabel));
+}
+
+pub fn transport_input_system(
+    keyboard_input: Res<Input<KeyCode>>,
+    entry: Res<TextEntry>,
+    mut transport: ResMut<Transport>,
+    mut label: Query<&mut Text, With<TransportLabel>>,
+) {
+    if entry.active() {
+        return
+    }
+
+    let t = &mut *transport;
+
+    if keyboard_input.just_pressed(KeyCode::Space) {
+        if t.playing {t.stop()} else {t.start()}
+    }
+    if keyboard_input.just_pressed(KeyCode::M) {
+        t.click = !t.click;
+    }
+    if keyboard_input.just_pressed(KeyCode::F7) {
+        t.set_bpm(t.bpm - 10.);
+    }
+    if keyboard_input.just_pressed(KeyCode::F8) {
+        t.set_bpm(t.bpm + 10.);
+    }
+    if keyboard_input.just_pressed(KeyCode::F12) {
+        t.beats_per_bar = (t.beats_per_bar - 1) % (MAX_BEATS_PER_BAR - 1) + 2;
+    }
+
+    if keyboard_input.any_just_pressed([KeyCode::Space, KeyCode::M, KeyCode::F7, KeyCode::F8, KeyCode::F12]) {
+        for mut text in &mut label {
+            text.sections[0].value = t.label();
+        }
+    }
+}
+
+// moves the transport forward, and clicks on each beat
+pub fn update_transport(
+    time: Res<Time>,
+    mut commands: Commands,
+    mut assets: ResMut<Assets<Synth>>,
+    mut transport: ResMut<Transport>,
+    mut label: Query<&mut Text, With<TransportLabel>>,
+) {
+    let t = &mut *transport;
+    if !t.playing {
+        return
+    }
+
+    // the first beat is counted on the frame the transport starts
+    if t.last_beat.is_some() {
+        t.position += time.delta_seconds() * t.bpm / 60.;
+    }
+
+    let beat = t.position as usize;
+    if t.last_beat == Some(beat) {
+        return
+    }
+    t.last_beat = Some(beat);
+
+    if t.click {
+        let frequency = match beat % t.beats_per_bar {
+            0 => ACCENT_FREQUENCY,
+            _ => CLICK_FREQUENCY,
+        };
+        commands.spawn(AudioSourceBundle {
+            source: assets.add(Synth::new(frequency, CLICK_SPECTRUM.into(), Tap::default()).decaying(CLICK_DECAY)),
+            settings: PlaybackSettings {
+                mode: PlaybackMode::Despawn,
+                ..Default::default()
+            }
+        });
+    }
+
+    for mut text in &mut label {
+        text.sections[0].value = t.label();
+    }
+}